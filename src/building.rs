@@ -0,0 +1,204 @@
+/// Building
+///
+/// Build mode turns the held placeable item into a world obj on the tile the player faces
+/// B toggles build mode and Space builds on an empty tile or takes apart what was built there before
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use iyes_loopless::prelude::*;
+
+use crate::{
+    entity_tile_pos::EntityTilePos,
+    hotbar::ActiveSlot,
+    inventory::{Inventory, InventoryUpdate},
+    item_util::{ItemDatabase, ItemId, ItemQuantity, SpawnItemEvent},
+    player::{Direction, Player, PlayerTarget, SystemOrder},
+    world_gen::{despawn_obj, offset_tile, place_built_obj, Blocking, Layer, WorldChanges, WorldTilesMut},
+    GameState,
+};
+
+const BUILD_TARGET_COLOR: Color = Color::rgb(0.4, 0.8, 1.0);
+
+pub struct BuildingPlugin;
+
+impl Plugin for BuildingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<BuildMode>()
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Running)
+                    .label(SystemOrder::Input)
+                    .before(SystemOrder::Logic)
+                    .with_system(toggle_build_mode)
+                    .with_system(build_action)
+                    .into(),
+            )
+            .add_system(
+                show_build_mode
+                    .run_in_state(GameState::Running)
+                    .label(SystemOrder::Graphic)
+                    .after(SystemOrder::Logic),
+            );
+    }
+}
+
+/// While building Space places the held item instead of harvesting
+#[derive(Resource, Default)]
+pub struct BuildMode(pub bool);
+
+impl BuildMode {
+    pub fn is_building(&self) -> bool {
+        self.0
+    }
+}
+
+/// A world obj the player built, taking it apart gives back the item it was built from
+#[derive(Component, Clone, Copy)]
+pub struct Built(pub ItemId);
+
+// The held item when it can be built with
+fn held_placeable(active: &ActiveSlot, inventory: &Inventory, item_db: &ItemDatabase) -> Option<ItemId> {
+    active
+        .held(inventory)
+        .filter(|id| item_db.items.get(id).is_some_and(|item| item.placeable.is_some()))
+}
+
+// B enters build mode, or leaves it again
+fn toggle_build_mode(
+    mut build_mode: ResMut<BuildMode>,
+    player_q: Query<(&ActiveSlot, &Inventory), With<Player>>,
+    item_db: Res<ItemDatabase>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::B) {
+        return;
+    }
+
+    if build_mode.is_building() {
+        build_mode.0 = false;
+        println!("left build mode");
+        return;
+    }
+
+    let (active, inventory) = match player_q.get_single() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in build mode fn"),
+    };
+    build_mode.0 = true;
+    match held_placeable(active, inventory, &item_db).and_then(|id| item_db.items.get(&id)) {
+        Some(item) => println!("building with {}", item.name),
+        None => println!("entered build mode, hold something placeable to build with it"),
+    }
+}
+
+/// Builds the held item on the tile the player faces, or takes apart what was built there
+/// Anything else standing on the tile, or a floor that blocks like water, is left alone
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn build_action(
+    mut commands: Commands,
+    build_mode: Res<BuildMode>,
+    mut changes: ResMut<WorldChanges>,
+    mut player_q: Query<(&EntityTilePos, &Direction, &ActiveSlot, &mut Inventory), With<Player>>,
+    mut world_tiles: WorldTilesMut,
+    objs_q: Query<(Option<&Built>, Option<&Inventory>), (With<TilePos>, Without<Player>)>,
+    blocking_q: Query<(), With<Blocking>>,
+    item_db: Res<ItemDatabase>,
+    mut ev_spawnitem: EventWriter<SpawnItemEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !build_mode.is_building() || !keeb.just_pressed(KeyCode::Space) {
+        return;
+    }
+
+    let (pos, direction, active, mut inventory) = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in build fn"),
+    };
+    let (dx, dy) = match *direction {
+        Direction::Up => (0, 1),
+        Direction::Down => (0, -1),
+        Direction::Left => (-1, 0),
+        Direction::Right => (1, 0),
+    };
+    let dest_tile = match offset_tile(&TilePos { x: pos.x, y: pos.y }, dx, dy) {
+        Some(tile_pos) => tile_pos,
+        None => return,
+    };
+    // Missing right after the world was rebuilt
+    let objs_tilemap = match world_tiles.tilemap(Layer::Objs, &dest_tile) {
+        Some(tilemap) => tilemap,
+        None => return,
+    };
+
+    // Only what was built can be taken apart, anything else standing there is in the way
+    match world_tiles
+        .get(Layer::Objs, &dest_tile)
+        .and_then(|obj| objs_q.get(obj).ok().map(|built| (obj, built)))
+    {
+        Some((obj, (Some(built), contents))) => {
+            despawn_obj(&mut commands, &mut world_tiles, obj, &dest_tile, None);
+            changes.placed_objs.remove(&dest_tile);
+            ev_spawnitem.send(SpawnItemEvent::from(dest_tile.x, dest_tile.y, built.0));
+            // Whatever a chest held spills out around it
+            for (id, amt) in contents.iter().flat_map(|inventory| inventory.slots().flatten()) {
+                ev_spawnitem.send(SpawnItemEvent::with_quantity(dest_tile.x, dest_tile.y, id, amt));
+            }
+            println!("took apart the built obj");
+            return;
+        }
+        Some((_, (None, _))) => {
+            println!("something is in the way");
+            return;
+        }
+        None => {}
+    }
+    let ground = world_tiles.get(Layer::Floor, &dest_tile);
+    if ground.is_some_and(|tile| blocking_q.contains(tile)) {
+        println!("can not build there");
+        return;
+    }
+
+    let build_item = match held_placeable(active, &inventory, &item_db) {
+        Some(id) => id,
+        None => {
+            println!("nothing placeable held to build with");
+            return;
+        }
+    };
+    let (name, placeable) = match item_db.items.get(&build_item) {
+        Some(item) => match item.placeable {
+            Some(placeable) => (item.name.as_str(), placeable),
+            None => return,
+        },
+        None => return,
+    };
+    if !inventory.remove_from_slot(active.0, &ItemQuantity(1)) {
+        return;
+    }
+
+    let built_entity = place_built_obj(&mut commands, &objs_tilemap, &dest_tile, build_item, placeable);
+    world_tiles.set(Layer::Objs, &dest_tile, built_entity);
+    commands.entity(objs_tilemap).add_child(built_entity);
+    changes.placed_objs.insert(dest_tile, build_item);
+    ev_invupdate.send(InventoryUpdate);
+    println!("built {name}");
+
+    if active.held(&inventory).is_none() {
+        println!("no {name} left to build with");
+    }
+}
+
+// Tints the player target while building so it's clear what Space does
+fn show_build_mode(build_mode: Res<BuildMode>, mut target_q: Query<&mut Sprite, With<PlayerTarget>>) {
+    if !build_mode.is_changed() {
+        return;
+    }
+
+    for mut sprite in target_q.iter_mut() {
+        sprite.color = if build_mode.is_building() {
+            BUILD_TARGET_COLOR
+        } else {
+            Color::WHITE
+        };
+    }
+}
@@ -80,7 +80,9 @@ fn zoom_camera(
         cam_lock.0 = !cam_lock.0;
         println!("Camlock set to {}", cam_lock.0)
     }
-    if cam_lock.0 {
+    // Without Ctrl the wheel goes through the hotbar
+    let zooming = keyboard_input.pressed(KeyCode::LControl) || keyboard_input.pressed(KeyCode::RControl);
+    if cam_lock.0 || !zooming {
         return;
     }
 
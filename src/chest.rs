@@ -0,0 +1,345 @@
+/// Chests
+///
+/// Built chests hold an inventory of their own, Space on one opens a two pane transfer menu
+/// that moves whole stacks between the player and the chest
+use bevy::{prelude::*, ui::widget::ImageMode};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    assets::{FontAssets, UiAssets},
+    interact::HarvestInteraction,
+    inventory::{Inventory, InventoryUpdate, INVENTORY_SLOTS},
+    item_util::{ItemDatabase, ItemId, ItemQuantity},
+    player::{Player, SystemOrder},
+    GameState,
+};
+
+pub const CHEST_SLOTS: usize = 18;
+const CHEST_LINES: usize = 18; // Lines in each pane of the transfer menu
+const POINTER_COLOR: Color = Color::rgb(0.6, 0.15, 0.1);
+
+pub struct ChestPlugin;
+
+impl Plugin for ChestPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_enter_system(GameState::GameLoading, create_chest_ui)
+            .add_event::<TransferStackEvent>()
+            .add_system(
+                open_chest
+                    .run_in_state(GameState::Running)
+                    .run_on_event::<HarvestInteraction>()
+                    .after(SystemOrder::Input),
+            )
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Menu)
+                    .run_if_resource_exists::<ChestMenu>()
+                    .with_system(chest_menu_input)
+                    .with_system(chest_menu_mouse)
+                    .with_system(transfer_stack)
+                    .with_system(ui_chest_update)
+                    .into(),
+            );
+    }
+}
+
+/// A built obj that keeps items, its `Inventory` sits on the same entity
+#[derive(Component)]
+pub struct Chest;
+
+/// The inventory a chest is built with, filled with what it held before its chunk unloaded
+pub fn chest_inventory(contents: &[(ItemId, ItemQuantity)], item_db: &ItemDatabase) -> Inventory {
+    let mut inventory = Inventory::with_slots(CHEST_SLOTS);
+    for (id, amt) in contents {
+        let left = inventory.add_item(*id, amt, item_db);
+        if left.0 > 0 {
+            println!("No room for {} of {:?} in the chest", left.0, id);
+        }
+    }
+    inventory
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ChestPane {
+    Player,
+    Chest,
+}
+
+impl ChestPane {
+    fn other(self) -> ChestPane {
+        match self {
+            ChestPane::Player => ChestPane::Chest,
+            ChestPane::Chest => ChestPane::Player,
+        }
+    }
+}
+
+/// Only exists while a chest is open
+#[derive(Resource)]
+struct ChestMenu {
+    chest: Entity,
+    pane: ChestPane,
+    pointer: usize, // slot in the pane's inventory
+}
+
+/// Moves the stack in a slot of one pane over to the other
+struct TransferStackEvent {
+    from: ChestPane,
+    slot: usize,
+}
+
+#[derive(Component)]
+struct ChestUi;
+
+#[derive(Component)]
+struct ChestLine(ChestPane, usize);
+
+// Space on a chest opens it, the harvest itself goes nowhere since chests can't be harvested
+fn open_chest(
+    mut commands: Commands,
+    mut chest_ui_q: Query<&mut Visibility, With<ChestUi>>,
+    chests_q: Query<(), With<Chest>>,
+    player_q: Query<Entity, With<Player>>,
+    mut ev_interact: EventReader<HarvestInteraction>,
+) {
+    for ev in ev_interact.iter() {
+        if chests_q.get(ev.harvested).is_err() || player_q.get(ev.harvester).is_err() {
+            continue;
+        }
+
+        if let Ok(mut chest_ui) = chest_ui_q.get_single_mut() {
+            chest_ui.is_visible = true;
+            commands.insert_resource(ChestMenu {
+                chest: ev.harvested,
+                pane: ChestPane::Chest,
+                pointer: 0,
+            });
+            commands.insert_resource(NextState(GameState::Menu));
+        }
+        return;
+    }
+}
+
+fn pane_slots(pane: ChestPane) -> usize {
+    match pane {
+        ChestPane::Player => INVENTORY_SLOTS,
+        ChestPane::Chest => CHEST_SLOTS,
+    }
+}
+
+// W/S picks a slot, A/D switches between the panes, Enter moves the stack over and Space closes the chest
+fn chest_menu_input(
+    mut commands: Commands,
+    mut menu: ResMut<ChestMenu>,
+    mut chest_ui_q: Query<&mut Visibility, With<ChestUi>>,
+    mut ev_transfer: EventWriter<TransferStackEvent>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    let slots = pane_slots(menu.pane);
+    if keeb.just_pressed(KeyCode::S) && menu.pointer + 1 < slots {
+        menu.pointer += 1;
+    } else if keeb.just_pressed(KeyCode::W) && menu.pointer > 0 {
+        menu.pointer -= 1;
+    } else if keeb.just_pressed(KeyCode::A) || keeb.just_pressed(KeyCode::D) {
+        menu.pane = menu.pane.other();
+        menu.pointer = menu.pointer.min(pane_slots(menu.pane) - 1);
+    } else if keeb.just_pressed(KeyCode::Return) {
+        ev_transfer.send(TransferStackEvent {
+            from: menu.pane,
+            slot: menu.pointer,
+        });
+    }
+
+    if keeb.just_pressed(KeyCode::Space) {
+        if let Ok(mut chest_ui) = chest_ui_q.get_single_mut() {
+            chest_ui.is_visible = false;
+        }
+        commands.remove_resource::<ChestMenu>();
+        commands.insert_resource(NextState(GameState::Running));
+    }
+}
+
+// Hovering a line points at it and clicking it moves the stack over
+fn chest_menu_mouse(
+    mut menu: ResMut<ChestMenu>,
+    lines_q: Query<(&Interaction, &ChestLine), Changed<Interaction>>,
+    mut ev_transfer: EventWriter<TransferStackEvent>,
+) {
+    let scroll = (menu.pointer + 1).saturating_sub(CHEST_LINES);
+    for (interaction, line) in lines_q.iter() {
+        // Lines of the other pane scroll with their own pointer, which isn't tracked
+        let slot = if line.0 == menu.pane { scroll + line.1 } else { line.1 };
+        match interaction {
+            Interaction::Hovered => {
+                menu.pane = line.0;
+                menu.pointer = slot;
+            }
+            Interaction::Clicked => {
+                menu.pane = line.0;
+                menu.pointer = slot;
+                ev_transfer.send(TransferStackEvent { from: line.0, slot });
+            }
+            Interaction::None => {}
+        }
+    }
+}
+
+// As much of the stack as fits goes over, the rest stays where it was
+fn transfer_stack(
+    menu: Res<ChestMenu>,
+    mut player_q: Query<&mut Inventory, With<Player>>,
+    mut chests_q: Query<&mut Inventory, (With<Chest>, Without<Player>)>,
+    item_db: Res<ItemDatabase>,
+    mut ev_transfer: EventReader<TransferStackEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+) {
+    let mut player_inv = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in chest fn"),
+    };
+    let mut chest_inv = match chests_q.get_mut(menu.chest) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for ev in ev_transfer.iter() {
+        let from_inv = match ev.from {
+            ChestPane::Player => &player_inv,
+            ChestPane::Chest => &chest_inv,
+        };
+        if from_inv.slot(ev.slot).is_none() {
+            continue;
+        }
+
+        let moved = match ev.from {
+            ChestPane::Player => player_inv.transfer_slot(ev.slot, &mut chest_inv, &item_db),
+            ChestPane::Chest => chest_inv.transfer_slot(ev.slot, &mut player_inv, &item_db),
+        };
+        if moved == 0 {
+            println!("no room to move that");
+            continue;
+        }
+        ev_invupdate.send(InventoryUpdate);
+    }
+}
+
+fn create_chest_ui(mut commands: Commands, font: Res<FontAssets>, elements: Res<UiAssets>) {
+    let text_style = TextStyle {
+        font: font.chunk.clone(),
+        font_size: 20.0,
+        color: Color::BLACK,
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+            ChestUi,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(ImageBundle {
+                    image: UiImage(elements.menubg.clone()),
+                    style: Style {
+                        align_self: AlignSelf::Center,
+                        position_type: PositionType::Absolute,
+                        size: Size::new(Val::Px(600.), Val::Px(600.)),
+                        ..default()
+                    },
+                    image_mode: ImageMode::KeepAspect,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    for (pane, left, title) in
+                        [(ChestPane::Player, 40., "Inventory"), (ChestPane::Chest, 310., "Chest")]
+                    {
+                        parent.spawn(TextBundle::from_section(title, text_style.clone()).with_style(Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(left),
+                                top: Val::Px(22.),
+                                ..default()
+                            },
+                            ..default()
+                        }));
+                        for i in 0..CHEST_LINES {
+                            let offset: f32 = i as f32 * 25.0;
+                            parent.spawn((
+                                TextBundle::from_section("", text_style.clone()).with_style(Style {
+                                    position_type: PositionType::Absolute,
+                                    position: UiRect {
+                                        left: Val::Px(left),
+                                        top: Val::Px(55. + offset),
+                                        ..default()
+                                    },
+                                    ..default()
+                                }),
+                                ChestLine(pane, i),
+                                Interaction::default(),
+                            ));
+                        }
+                    }
+
+                    parent.spawn(
+                        TextBundle::from_section("W/S pick  A/D switch  Enter move  Space close", text_style)
+                            .with_style(Style {
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    left: Val::Px(40.),
+                                    top: Val::Px(545.),
+                                    ..default()
+                                },
+                                ..default()
+                            }),
+                    );
+                });
+        });
+}
+
+// Both panes scroll along with the pointer when it is in them
+fn ui_chest_update(
+    menu: Res<ChestMenu>,
+    mut ui_lines_q: Query<(&mut Text, &ChestLine)>,
+    player_q: Query<&Inventory, With<Player>>,
+    chests_q: Query<&Inventory, (With<Chest>, Without<Player>)>,
+    item_db: Res<ItemDatabase>,
+) {
+    let player_inv = match player_q.get_single() {
+        Ok(e) => e,
+        Err(_) => panic!("Could not fetch the player's inventory!!!"),
+    };
+    let chest_inv = match chests_q.get(menu.chest) {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for (mut text, line) in ui_lines_q.iter_mut() {
+        let pointed = line.0 == menu.pane;
+        let scroll = if pointed {
+            (menu.pointer + 1).saturating_sub(CHEST_LINES)
+        } else {
+            0
+        };
+        let slot = scroll + line.1;
+        let inventory = match line.0 {
+            ChestPane::Player => player_inv,
+            ChestPane::Chest => chest_inv,
+        };
+
+        let cursor = if pointed && slot == menu.pointer { ">" } else { " " };
+        text.sections[0].value = match inventory.slot(slot) {
+            Some((id, amt)) => {
+                let name = item_db.items.get(&id).map_or("undefined", |item| item.name.as_str());
+                format!("{cursor} {name: <18}x{:>3}", amt.0)
+            }
+            None => cursor.to_string(),
+        };
+        text.sections[0].style.color = if pointed && slot == menu.pointer {
+            POINTER_COLOR
+        } else {
+            Color::BLACK
+        };
+    }
+}
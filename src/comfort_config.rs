@@ -1,14 +1,37 @@
 use serde::Deserialize;
+use std::env;
 use std::error::Error;
+use std::fmt;
 use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
 
-#[derive(Deserialize)]
+/// Everything in config/worldgen.toml, load it with `load_config` so it gets validated
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
 pub struct ComfortConfig {
     pub terrainperlin: FractalSettings,
     pub treeperlin: FractalSettings,
+    pub temperatureperlin: FractalSettings,
+    pub moistureperlin: FractalSettings,
+    #[serde(default)]
+    pub world: WorldSettings,
+    #[serde(default)]
+    pub biome: Vec<BiomeSettings>,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct WorldSettings {
+    // When set every world will be generated from this seed instead of a random one
+    pub seed: Option<u64>,
+    // Rebuild the world as soon as the noise or biome settings in the file change, for tuning them in game
+    #[serde(default)]
+    pub regenerate_on_change: bool,
+}
+
+#[derive(Deserialize, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
 pub struct FractalSettings {
     pub octaves: i32,
     pub gain: f32,
@@ -16,12 +39,315 @@ pub struct FractalSettings {
     pub frequency: f32,
 }
 
-pub fn load_settings(preset: &str) -> Result<FractalSettings, Box<dyn Error>> {
-    let contents = fs::read_to_string("config/worldgen.toml")?;
-    let decoded: ComfortConfig = toml::from_str(&contents).unwrap();
-    match preset {
-        "terrainperlin" => Ok(decoded.terrainperlin),
-        "treeperlin" => Ok(decoded.treeperlin),
-        _ => Ok(decoded.terrainperlin),
+// Built-in settings for when there is no config file, the biome list is empty so the whole world is meadow
+impl Default for ComfortConfig {
+    fn default() -> Self {
+        ComfortConfig {
+            terrainperlin: FractalSettings {
+                octaves: 6,
+                gain: 0.05,
+                lacunarity: 0.7,
+                frequency: 1.9,
+            },
+            treeperlin: FractalSettings {
+                octaves: 6,
+                gain: 0.1,
+                lacunarity: 0.2,
+                frequency: 1.5,
+            },
+            temperatureperlin: FractalSettings {
+                octaves: 3,
+                gain: 0.5,
+                lacunarity: 2.0,
+                frequency: 0.6,
+            },
+            moistureperlin: FractalSettings {
+                octaves: 3,
+                gain: 0.5,
+                lacunarity: 2.0,
+                frequency: 0.5,
+            },
+            world: WorldSettings::default(),
+            biome: Vec::new(),
+        }
+    }
+}
+
+impl ComfortConfig {
+    /// True when both configs generate the same world for the same seed
+    pub fn generates_like(&self, other: &ComfortConfig) -> bool {
+        self.terrainperlin == other.terrainperlin
+            && self.treeperlin == other.treeperlin
+            && self.temperatureperlin == other.temperatureperlin
+            && self.moistureperlin == other.moistureperlin
+            && self.biome == other.biome
+    }
+}
+
+impl FractalSettings {
+    // Err holds the key and what is wrong with it
+    fn validate(&self) -> Result<(), (&'static str, String)> {
+        if self.octaves <= 0 {
+            return Err(("octaves", format!("must be above 0, found {}", self.octaves)));
+        }
+        let positive = [
+            ("gain", self.gain),
+            ("lacunarity", self.lacunarity),
+            ("frequency", self.frequency),
+        ];
+        for (key, value) in positive {
+            if value <= 0.0 || !value.is_finite() {
+                return Err((key, format!("must be above 0, found {value}")));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A `[[biome]]` table, decides what the world looks like wherever its temperature and moisture ranges match
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeSettings {
+    pub name: String,
+    // Normalized noise ranges [min, max] between 0 and 1 this biome covers
+    pub temperature: [f32; 2],
+    pub moisture: [f32; 2],
+    // Terrain atlas index of the plain floor tile
+    pub floor: u32,
+    // Terrain atlas indices that sometimes replace the plain floor
+    #[serde(default)]
+    pub foliage: Vec<u32>,
+    #[serde(default)]
+    pub foliage_chance: u32, // percent
+    // Multiplied with the floor sprites
+    #[serde(default = "no_tint")]
+    pub tint: [f32; 3],
+    #[serde(default)]
+    pub tree_chance: u32, // percent of the spots the tree noise allows that actually grow a tree
+    #[serde(default)]
+    pub rock_chance: u32, // percent
+    #[serde(default)]
+    pub mound_chance: u32, // percent
+    // World objs atlas indices
+    #[serde(default)]
+    pub flowers: Vec<u32>,
+    #[serde(default)]
+    pub flower_chance: u32, // percent
+    // Items lying around the first time a chunk gets generated
+    #[serde(default)]
+    pub items: Vec<BiomeItem>,
+}
+
+#[derive(Deserialize, Debug, PartialEq, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct BiomeItem {
+    pub id: u32,
+    pub chance: f32, // percent per tile
+}
+
+fn no_tint() -> [f32; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+// Plain grassland the world was made of before biomes existed, used wherever no configured biome matches
+impl Default for BiomeSettings {
+    fn default() -> Self {
+        BiomeSettings {
+            name: "meadow".to_string(),
+            temperature: [0.0, 1.0],
+            moisture: [0.0, 1.0],
+            floor: 0,
+            foliage: vec![1, 2, 3, 4],
+            foliage_chance: 20,
+            tint: no_tint(),
+            tree_chance: 100,
+            rock_chance: 0,
+            mound_chance: 0,
+            flowers: vec![2, 3, 4, 5, 6, 7],
+            flower_chance: 3,
+            items: Vec::new(),
+        }
+    }
+}
+
+impl BiomeSettings {
+    // Err holds the key and what is wrong with it
+    fn validate(&self) -> Result<(), (&'static str, String)> {
+        for (key, [min, max]) in [("temperature", self.temperature), ("moisture", self.moisture)] {
+            if !(0.0..=1.0).contains(&min) || !(0.0..=1.0).contains(&max) || min > max {
+                return Err((
+                    key,
+                    format!("must be a [min, max] range between 0 and 1, found [{min}, {max}]"),
+                ));
+            }
+        }
+        let chances = [
+            ("foliage_chance", self.foliage_chance),
+            ("tree_chance", self.tree_chance),
+            ("rock_chance", self.rock_chance),
+            ("mound_chance", self.mound_chance),
+            ("flower_chance", self.flower_chance),
+        ];
+        for (key, chance) in chances {
+            if chance > 100 {
+                return Err((key, format!("is a percentage and can not be above 100, found {chance}")));
+            }
+        }
+        if self.tint.iter().any(|channel| !(0.0..=1.0).contains(channel)) {
+            return Err((
+                "tint",
+                format!("channels must be between 0 and 1, found {:?}", self.tint),
+            ));
+        }
+        // The items share one roll per tile, so together they can not go over 100 percent
+        let item_chances: f32 = self.items.iter().map(|item| item.chance).sum();
+        if self.items.iter().any(|item| item.chance < 0.0 || item.chance.is_nan()) || item_chances > 100.0 {
+            return Err((
+                "items",
+                "chances must be positive and add up to 100 at most".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn contains(&self, temperature: f32, moisture: f32) -> bool {
+        (self.temperature[0]..=self.temperature[1]).contains(&temperature)
+            && (self.moisture[0]..=self.moisture[1]).contains(&moisture)
+    }
+}
+
+/// config/worldgen.toml unless another file is passed with `--config <file>`
+pub fn config_path() -> PathBuf {
+    let args: Vec<String> = env::args().collect();
+    match args
+        .iter()
+        .position(|arg| arg == "--config")
+        .and_then(|idx| args.get(idx + 1))
+    {
+        Some(path) => PathBuf::from(path),
+        None => PathBuf::from("config/worldgen.toml"),
+    }
+}
+
+/// Reads and validates the worldgen config, a missing file is not an error and gives the built-in settings
+pub fn load_config() -> Result<ComfortConfig, ConfigError> {
+    let path = config_path();
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            println!(
+                "{} not found, using the built-in world generation settings",
+                path.display()
+            );
+            return Ok(ComfortConfig::default());
+        }
+        Err(source) => return Err(ConfigError::Io { path, source }),
+    };
+    parse_config(&path, &contents)
+}
+
+pub fn parse_config(path: &Path, contents: &str) -> Result<ComfortConfig, ConfigError> {
+    let config: ComfortConfig = match toml::from_str(contents) {
+        Ok(config) => config,
+        Err(source) => {
+            return Err(ConfigError::Parse {
+                path: path.to_path_buf(),
+                source,
+            })
+        }
+    };
+
+    // Validation only knows the table a value is in, so the error points at the table's header
+    let invalid = |header: &str, nth: usize, key: String, reason: String| ConfigError::Invalid {
+        path: path.to_path_buf(),
+        line: header_line(contents, header, nth),
+        key,
+        reason,
+    };
+    let presets = [
+        ("terrainperlin", &config.terrainperlin),
+        ("treeperlin", &config.treeperlin),
+        ("temperatureperlin", &config.temperatureperlin),
+        ("moistureperlin", &config.moistureperlin),
+    ];
+    for (name, settings) in presets {
+        if let Err((key, reason)) = settings.validate() {
+            return Err(invalid(&format!("[{name}]"), 0, format!("{name}.{key}"), reason));
+        }
+    }
+    for (idx, biome) in config.biome.iter().enumerate() {
+        if let Err((key, reason)) = biome.validate() {
+            return Err(invalid(
+                "[[biome]]",
+                idx,
+                format!("biome {} ({}).{key}", idx + 1, biome.name),
+                reason,
+            ));
+        }
+    }
+
+    Ok(config)
+}
+
+// 1 based line of the nth time a table header shows up in the file
+fn header_line(contents: &str, header: &str, nth: usize) -> Option<usize> {
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| line.trim() == header)
+        .nth(nth)
+        .map(|(idx, _)| idx + 1)
+}
+
+/// Why the worldgen config could not be used
+#[derive(Debug)]
+pub enum ConfigError {
+    // The file exists but could not be read
+    Io {
+        path: PathBuf,
+        source: io::Error,
+    },
+    // Not valid toml or keys are missing, unknown or of the wrong type, the toml error has the line
+    Parse {
+        path: PathBuf,
+        source: toml::de::Error,
+    },
+    // Parsed fine but a value is out of range
+    Invalid {
+        path: PathBuf,
+        line: Option<usize>,
+        key: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => write!(f, "could not read {}, {source}", path.display()),
+            ConfigError::Parse { path, source } => write!(f, "{} is not a valid config, {source}", path.display()),
+            ConfigError::Invalid {
+                path,
+                line: Some(line),
+                key,
+                reason,
+            } => write!(f, "{}:{line}: {key} {reason}", path.display()),
+            ConfigError::Invalid {
+                path,
+                line: None,
+                key,
+                reason,
+            } => write!(f, "{}: {key} {reason}", path.display()),
+        }
+    }
+}
+
+impl Error for ConfigError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ConfigError::Io { source, .. } => Some(source),
+            ConfigError::Parse { source, .. } => Some(source),
+            ConfigError::Invalid { .. } => None,
+        }
     }
 }
@@ -9,3 +9,10 @@ pub const FLOWER_YELLOW_BUNCH: u32 = 4;
 pub const FLOWER_WHITE_SMALL1: u32 = 5;
 pub const FLOWER_WHITE_SMALL2: u32 = 6;
 pub const MUSHROOM_RED_SMALL: u32 = 7;
+pub const ROCK: u32 = 8;
+pub const WOOD_WALL: u32 = 9;
+pub const WORKBENCH: u32 = 10;
+pub const CAMPFIRE: u32 = 11;
+pub const CHEST: u32 = 12;
+pub const WOOD_FLOOR: u32 = 13;
+pub const DIRT_MOUND: u32 = 14;
@@ -1,26 +1,52 @@
-use bevy::{prelude::*, utils::HashMap};
+use bevy::{prelude::*, ui::widget::ImageMode, utils::HashMap};
 use iyes_loopless::prelude::*;
+use rand::Rng;
 use serde::Deserialize;
-use std::{error::Error, fs};
+use std::{collections::VecDeque, error::Error, fs, time::Duration};
 
 use crate::{
-    inventory::Inventory,
-    item_util::{ItemId, ItemQuantity},
+    assets::{FontAssets, UiAssets},
+    entity_tile_pos::EntityTilePos,
+    inventory::{Inventory, InventoryUpdate},
+    item_util::{ItemDatabase, ItemId, ItemQuantity, SpawnItemEvent},
     player::Player,
+    world_gen::WorldTilePos,
     GameState,
 };
 
+const CRAFTING_SLOTS: usize = 18; // Recipes shown at once, the list scrolls to keep the selected one in view
+
 pub struct CraftingPlugin;
 impl Plugin for CraftingPlugin {
     fn build(&self, app: &mut App) {
         app.add_enter_system(GameState::GameLoading, init_recipe_database)
+            .add_enter_system(GameState::GameLoading, create_crafting_ui)
+            .add_enter_system(GameState::GameLoading, create_crafting_progress_ui)
+            // Jobs are queued and cancelled from the menu too, while the game is paused
             .add_system(
                 handle_crafting_event
-                    .run_in_state(GameState::Running)
+                    .run_not_in_state(GameState::AssetLoading)
                     .run_on_event::<CraftItemEvent>(),
             )
-            .add_system(testcraft.run_in_state(GameState::Running))
-            .add_event::<CraftItemEvent>();
+            .add_system(
+                cancel_crafting_job
+                    .run_not_in_state(GameState::AssetLoading)
+                    .run_on_event::<CancelCraftEvent>(),
+            )
+            .add_system(progress_crafting.run_in_state(GameState::Running))
+            .add_system(ui_crafting_progress.run_not_in_state(GameState::AssetLoading))
+            .add_system(open_crafting_menu.run_in_state(GameState::Running))
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Menu)
+                    .run_if_resource_exists::<CraftingMenu>()
+                    .with_system(crafting_menu_input)
+                    .with_system(ui_crafting_update)
+                    .into(),
+            )
+            .add_event::<CraftItemEvent>()
+            .add_event::<CancelCraftEvent>()
+            .add_event::<CraftingProgressEvent>();
     }
 }
 
@@ -29,43 +55,207 @@ struct CraftItemEvent {
     recipe: RecipeId, // what is being crafted
 }
 
+/// Cancels the job the entity queued last and gives back what it took
+struct CancelCraftEvent {
+    who: Entity,
+}
+
+/// Sent every frame the job at the front of a queue moves along, progress goes from 0 to 1
+/// and reaches 1 the frame the outputs are handed over
+pub struct CraftingProgressEvent {
+    pub who: Entity,
+    pub output: ItemId, // the first output of the recipe
+    pub progress: f32,
+    pub queued: usize, // jobs waiting behind this one
+}
+
+/// Jobs an entity is crafting, one after the other in the order they were queued
+#[derive(Component, Default)]
+pub struct CraftingQueue {
+    jobs: VecDeque<CraftingJob>,
+}
+
+struct CraftingJob {
+    recipe: RecipeId,
+    timer: Timer,
+    // Taken out of the inventory when queued so they can't be spent twice, handed back on cancel
+    reserved: Vec<Ingredient>,
+    // Stay in the inventory, so they are checked for again before the outputs are handed over
+    catalysts: Vec<Ingredient>,
+    outputs: Vec<Output>,
+}
+
+impl CraftingQueue {
+    pub fn is_empty(&self) -> bool {
+        self.jobs.is_empty()
+    }
+
+    // Every ingredient held by the queued jobs
+    pub fn reserved(&self) -> impl Iterator<Item = (ItemId, ItemQuantity)> + '_ {
+        self.jobs
+            .iter()
+            .flat_map(|job| job.reserved.iter())
+            .map(|ingredient| (ingredient.item_id, ingredient.item_quantity))
+    }
+}
+
+/// Checks the recipe can be crafted here and now, then reserves its ingredients and queues it
 fn handle_crafting_event(
-    mut inventory_q: Query<&mut Inventory, With<Player>>,
+    mut crafter_q: Query<(&mut Inventory, &mut CraftingQueue, &EntityTilePos)>,
+    stations_q: Query<(&Station, &WorldTilePos)>,
     mut ev_crafting: EventReader<CraftItemEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
     recipe_db: Res<RecipeDatabase>,
 ) {
     for ev in ev_crafting.iter() {
-        if let Ok(mut inventory) = inventory_q.get_mut(ev.who) {
+        if let Ok((mut inventory, mut queue, pos)) = crafter_q.get_mut(ev.who) {
             if let Some(recipe) = recipe_db.recipes.get(&ev.recipe) {
-                for ingredient in recipe.ingredients.iter() {
-                    if !inventory.contains_item(ingredient.item_id, &ingredient.item_quantity) {
-                        println!("cannot craft, not enough {:?}", ingredient.item_id);
-                        return;
+                if !station_nearby(recipe, pos, &stations_q) {
+                    if let Some(kind) = recipe.station {
+                        println!("cannot craft, needs a {kind:?} nearby");
                     }
+                    continue;
                 }
-                for ingredient in recipe.ingredients.iter() {
+                // Only skip this event, later ones in the frame may still be affordable
+                if let Some(missing) = recipe
+                    .ingredients
+                    .iter()
+                    .find(|ingredient| !inventory.contains_item(ingredient.item_id, &ingredient.item_quantity))
+                {
+                    println!("cannot craft, not enough {:?}", missing.item_id);
+                    continue;
+                }
+                // Catalysts only have to be held, they stay in the inventory
+                let (catalysts, reserved): (Vec<Ingredient>, Vec<Ingredient>) = recipe
+                    .ingredients
+                    .iter()
+                    .cloned()
+                    .partition(|ingredient| ingredient.catalyst);
+                for ingredient in reserved.iter() {
                     inventory.remove_item(ingredient.item_id, &ingredient.item_quantity);
                 }
-                println!("crafted {:?}", recipe.id);
-                inventory.add_item(recipe.output_id, &recipe.output_amt);
+                queue.jobs.push_back(CraftingJob {
+                    recipe: recipe.id,
+                    timer: Timer::new(Duration::from_secs_f32(recipe.duration), TimerMode::Once),
+                    reserved,
+                    catalysts,
+                    outputs: recipe.outputs.clone(),
+                });
+                println!("queued {:?}, {} in the queue", recipe.id, queue.jobs.len());
+                ev_invupdate.send(InventoryUpdate);
             }
         }
     }
 }
 
-fn testcraft(
-    keeb: Res<Input<KeyCode>>,
-    player_q: Query<Entity, With<Player>>,
-    mut ev_crafting: EventWriter<CraftItemEvent>,
+// Hands the items to the crafter, what does not fit in the inventory is dropped at their feet
+fn give_to_crafter(
+    inventory: &mut Inventory,
+    pos: &EntityTilePos,
+    id: ItemId,
+    amt: &ItemQuantity,
+    item_db: &ItemDatabase,
+    ev_spawnitem: &mut EventWriter<SpawnItemEvent>,
 ) {
-    if !keeb.just_pressed(KeyCode::Key7) {
-        return;
+    let left = inventory.add_item(id, amt, item_db);
+    if left.0 > 0 {
+        ev_spawnitem.send(SpawnItemEvent::with_quantity(pos.x, pos.y, id, left));
     }
-    if let Ok(player) = player_q.get_single() {
-        ev_crafting.send(CraftItemEvent {
-            who: player,
-            recipe: RecipeId(1),
+}
+
+fn cancel_crafting_job(
+    mut crafter_q: Query<(&mut Inventory, &mut CraftingQueue, &EntityTilePos)>,
+    mut ev_cancel: EventReader<CancelCraftEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+    mut ev_spawnitem: EventWriter<SpawnItemEvent>,
+    item_db: Res<ItemDatabase>,
+) {
+    for ev in ev_cancel.iter() {
+        if let Ok((mut inventory, mut queue, pos)) = crafter_q.get_mut(ev.who) {
+            if let Some(job) = queue.jobs.pop_back() {
+                for ingredient in job.reserved.iter() {
+                    give_to_crafter(
+                        &mut inventory,
+                        pos,
+                        ingredient.item_id,
+                        &ingredient.item_quantity,
+                        &item_db,
+                        &mut ev_spawnitem,
+                    );
+                }
+                println!("cancelled {:?}", job.recipe);
+                ev_invupdate.send(InventoryUpdate);
+            }
+        }
+    }
+}
+
+// Only the job at the front of each queue makes progress, and only while the game runs
+fn progress_crafting(
+    mut crafter_q: Query<(Entity, &mut Inventory, &mut CraftingQueue, &EntityTilePos)>,
+    time: Res<Time>,
+    item_db: Res<ItemDatabase>,
+    mut ev_progress: EventWriter<CraftingProgressEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+    mut ev_spawnitem: EventWriter<SpawnItemEvent>,
+) {
+    for (crafter, mut inventory, mut queue, pos) in crafter_q.iter_mut() {
+        let queued = queue.jobs.len().saturating_sub(1);
+        let job = match queue.jobs.front_mut() {
+            Some(job) => job,
+            None => continue,
+        };
+
+        job.timer.tick(time.delta());
+        // Instant recipes have no duration to take a percentage of
+        let progress = if job.timer.finished() { 1.0 } else { job.timer.percent() };
+        ev_progress.send(CraftingProgressEvent {
+            who: crafter,
+            output: job.outputs[0].item_id,
+            progress,
+            queued,
         });
+
+        if !job.timer.finished() {
+            continue;
+        }
+        // A catalyst dropped while the job ran fails the job, the ingredients it took are given back
+        if let Some(missing) = job
+            .catalysts
+            .iter()
+            .find(|catalyst| !inventory.contains_item(catalyst.item_id, &catalyst.item_quantity))
+        {
+            println!(
+                "could not craft {:?}, {:?} is no longer held",
+                job.recipe, missing.item_id
+            );
+            for ingredient in job.reserved.iter() {
+                give_to_crafter(
+                    &mut inventory,
+                    pos,
+                    ingredient.item_id,
+                    &ingredient.item_quantity,
+                    &item_db,
+                    &mut ev_spawnitem,
+                );
+            }
+        } else {
+            // Byproducts are rolled for once the job is done
+            let mut rng = rand::thread_rng();
+            for output in job.outputs.iter().filter(|output| rng.gen_bool(output.chance)) {
+                give_to_crafter(
+                    &mut inventory,
+                    pos,
+                    output.item_id,
+                    &output.item_quantity,
+                    &item_db,
+                    &mut ev_spawnitem,
+                );
+            }
+            println!("crafted {:?}", job.recipe);
+        }
+        queue.jobs.pop_front();
+        ev_invupdate.send(InventoryUpdate);
     }
 }
 
@@ -74,42 +264,434 @@ fn testcraft(
 pub struct Recipe {
     id: RecipeId,
     ingredients: Vec<Ingredient>,
-    output_id: ItemId,
-    output_amt: ItemQuantity,
+    outputs: Vec<Output>,
+    #[serde(default)]
+    duration: f32, // seconds it takes to craft
+    #[serde(default)]
+    station: Option<StationKind>, // when set the recipe can only be crafted next to that kind of station
+}
+
+/// A built world obj that recipes can require the crafter to stand close to
+#[derive(Deserialize, Debug, Component, Clone, Copy)]
+pub struct Station {
+    pub kind: StationKind,
+    pub radius: u32, // tiles in every direction, diagonals included, the crafter can be away from it
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StationKind {
+    Workbench,
+    Campfire,
+}
+
+// True when the recipe needs no station or the crafter is within reach of one of the right kind
+fn station_nearby(recipe: &Recipe, pos: &EntityTilePos, stations_q: &Query<(&Station, &WorldTilePos)>) -> bool {
+    let kind = match recipe.station {
+        Some(kind) => kind,
+        None => return true,
+    };
+    stations_q.iter().any(|(station, station_pos)| {
+        station.kind == kind
+            && pos.x.abs_diff(station_pos.0.x) <= station.radius
+            && pos.y.abs_diff(station_pos.0.y) <= station.radius
+    })
 }
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Ingredient {
     item_id: ItemId,
     item_quantity: ItemQuantity,
+    #[serde(default)]
+    catalyst: bool, // needed to craft but never used up, like the hatchet that cuts planks
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Output {
+    item_id: ItemId,
+    item_quantity: ItemQuantity,
+    // Odds of it turning up between 0 and 1, anything below 1 is a byproduct
+    #[serde(default = "always")]
+    chance: f64,
+}
+
+fn always() -> f64 {
+    1.0
 }
 
 #[derive(Deserialize, Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub struct RecipeId(u32);
 
-// Maps all items to a unique u32, loaded on startup and should not be mutated at runtime
+pub const RECIPES_FILE: &str = "assets/items/comfort_recipes.json";
+
+// Maps all recipes to a unique u32, loaded on startup and replaced whenever the json changes
 #[derive(Resource)]
 pub struct RecipeDatabase {
     pub recipes: HashMap<RecipeId, Recipe>,
 }
 
 fn init_recipe_database(mut commands: Commands) {
-    let recipes = match load_from_json("comfort_recipes") {
-        Ok(recipes) => recipes,
+    match load_recipe_database() {
+        Ok(recipe_db) => commands.insert_resource(recipe_db),
         Err(err) => panic!("Could not load recipes from json, {err}\n"),
     };
+}
 
+pub fn load_recipe_database() -> Result<RecipeDatabase, Box<dyn Error>> {
     let mut recipe_db = HashMap::new();
-    for recipe in recipes {
+    for recipe in load_from_json()? {
+        if recipe.duration < 0.0 || !recipe.duration.is_finite() {
+            return Err(format!("{:?} needs a duration of 0 or more seconds", recipe.id).into());
+        }
+        if recipe.outputs.is_empty() {
+            return Err(format!("{:?} needs at least one output", recipe.id).into());
+        }
+        if recipe
+            .outputs
+            .iter()
+            .any(|output| !(0.0..=1.0).contains(&output.chance))
+        {
+            return Err(format!("{:?} has an output with a chance outside of 0 to 1", recipe.id).into());
+        }
         recipe_db.insert(recipe.id, recipe);
     }
-
-    commands.insert_resource(RecipeDatabase { recipes: recipe_db });
+    Ok(RecipeDatabase { recipes: recipe_db })
 }
 
-// Attempts to load item definitions from a json file
-fn load_from_json(name: &str) -> Result<Vec<Recipe>, Box<dyn Error>> {
-    let contents = fs::read_to_string(format!("assets/items/{name}.json"))?;
+// Attempts to load recipe definitions from a json file
+fn load_from_json() -> Result<Vec<Recipe>, Box<dyn Error>> {
+    let contents = fs::read_to_string(RECIPES_FILE)?;
     let recipes: Vec<Recipe> = serde_json::from_str(&contents)?;
     Ok(recipes)
 }
+
+//=====> Crafting menu
+/// Open while the player is picking something to craft
+#[derive(Resource)]
+struct CraftingMenu {
+    selected: usize,
+}
+
+#[derive(Component)]
+struct CraftingUi;
+
+#[derive(Component)]
+struct CraftingSlot(usize);
+
+fn create_crafting_ui(mut commands: Commands, font: Res<FontAssets>, elements: Res<UiAssets>) {
+    let text_style = TextStyle {
+        font: font.chunk.clone(),
+        font_size: 24.0,
+        color: Color::BLACK,
+    };
+
+    let menu_bg_style = Style {
+        align_self: AlignSelf::Center,
+        position_type: PositionType::Absolute,
+        size: Size::new(Val::Px(600.), Val::Px(600.)),
+        ..default()
+    };
+
+    commands
+        .spawn((
+            NodeBundle {
+                transform: Transform::from_xyz(0., 0., 80.),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+            CraftingUi,
+        ))
+        .with_children(|parent| {
+            parent
+                .spawn(ImageBundle {
+                    image: UiImage(elements.menubg.clone()),
+                    style: menu_bg_style,
+                    image_mode: ImageMode::KeepAspect,
+                    ..default()
+                })
+                .with_children(|parent| {
+                    // A line for every recipe that fits
+                    for i in 0..CRAFTING_SLOTS {
+                        let offset: f32 = i as f32 * 25.0;
+                        parent.spawn((
+                            TextBundle::from_section("", text_style.clone()).with_style(Style {
+                                align_self: AlignSelf::Center,
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    left: Val::Px(50.),
+                                    top: Val::Px(49. + offset),
+                                    ..default()
+                                },
+                                ..default()
+                            }),
+                            CraftingSlot(i),
+                        ));
+                    }
+                });
+        });
+}
+
+// Every recipe ordered by id, so the list stays put between frames
+fn sorted_recipes(recipe_db: &RecipeDatabase) -> Vec<&Recipe> {
+    let mut recipes: Vec<&Recipe> = recipe_db.recipes.values().collect();
+    recipes.sort_by_key(|recipe| recipe.id.0);
+    recipes
+}
+
+fn can_afford(recipe: &Recipe, inventory: &Inventory) -> bool {
+    recipe
+        .ingredients
+        .iter()
+        .all(|ingredient| inventory.contains_item(ingredient.item_id, &ingredient.item_quantity))
+}
+
+fn item_name(item_db: &ItemDatabase, id: ItemId) -> &str {
+    match item_db.items.get(&id) {
+        Some(info) => info.name.as_str(),
+        None => "undefined",
+    }
+}
+
+// C opens the crafting menu and pauses the game like the inventory does
+fn open_crafting_menu(
+    mut commands: Commands,
+    mut crafting_ui_q: Query<&mut Visibility, With<CraftingUi>>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::C) {
+        return;
+    }
+
+    if let Ok(mut crafting_ui) = crafting_ui_q.get_single_mut() {
+        crafting_ui.is_visible = true;
+        commands.insert_resource(CraftingMenu { selected: 0 });
+        commands.insert_resource(NextState(GameState::Menu));
+    }
+}
+
+// W/S picks a recipe, Enter queues it, Backspace cancels the last queued job and C closes the menu again
+#[allow(clippy::too_many_arguments)]
+fn crafting_menu_input(
+    mut commands: Commands,
+    mut menu: ResMut<CraftingMenu>,
+    mut crafting_ui_q: Query<&mut Visibility, With<CraftingUi>>,
+    player_q: Query<Entity, With<Player>>,
+    recipe_db: Res<RecipeDatabase>,
+    mut ev_crafting: EventWriter<CraftItemEvent>,
+    mut ev_cancel: EventWriter<CancelCraftEvent>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    let recipes = sorted_recipes(&recipe_db);
+    // Recipes can be reloaded while the menu is open
+    menu.selected = menu.selected.min(recipes.len().saturating_sub(1));
+
+    if keeb.just_pressed(KeyCode::S) && menu.selected + 1 < recipes.len() {
+        menu.selected += 1;
+    } else if keeb.just_pressed(KeyCode::W) && menu.selected > 0 {
+        menu.selected -= 1;
+    }
+
+    if keeb.just_pressed(KeyCode::Return) {
+        if let (Some(recipe), Ok(player)) = (recipes.get(menu.selected), player_q.get_single()) {
+            ev_crafting.send(CraftItemEvent {
+                who: player,
+                recipe: recipe.id,
+            });
+        }
+    }
+
+    if keeb.just_pressed(KeyCode::Back) {
+        if let Ok(player) = player_q.get_single() {
+            ev_cancel.send(CancelCraftEvent { who: player });
+        }
+    }
+
+    if keeb.just_pressed(KeyCode::C) {
+        if let Ok(mut crafting_ui) = crafting_ui_q.get_single_mut() {
+            crafting_ui.is_visible = false;
+        }
+        commands.remove_resource::<CraftingMenu>();
+        commands.insert_resource(NextState(GameState::Running));
+    }
+}
+
+/// Lists the recipes with what they make and what they take, the ones the player can't afford
+/// or is too far from a station for are greyed out
+fn ui_crafting_update(
+    menu: Res<CraftingMenu>,
+    mut ui_slots_q: Query<(&mut Text, &CraftingSlot)>,
+    inv_q: Query<(&Inventory, &EntityTilePos), With<Player>>,
+    stations_q: Query<(&Station, &WorldTilePos)>,
+    recipe_db: Res<RecipeDatabase>,
+    item_db: Res<ItemDatabase>,
+) {
+    let (player_inv, player_pos) = match inv_q.get_single() {
+        Ok(e) => e,
+        Err(_) => panic!("Could not fetch the player's inventory!!!"),
+    };
+
+    let recipes = sorted_recipes(&recipe_db);
+    let scroll = (menu.selected + 1).saturating_sub(CRAFTING_SLOTS);
+    for (mut text, slot) in ui_slots_q.iter_mut() {
+        let idx = scroll + slot.0;
+        let recipe = match recipes.get(idx) {
+            Some(recipe) => recipe,
+            None => {
+                text.sections[0].value = String::new();
+                continue;
+            }
+        };
+
+        let outputs: Vec<String> = recipe
+            .outputs
+            .iter()
+            .map(|output| {
+                let chance = match output.chance {
+                    chance if chance < 1.0 => format!(" ({:.0}%)", chance * 100.),
+                    _ => String::new(),
+                };
+                format!(
+                    "{} x{}{chance}",
+                    item_name(&item_db, output.item_id),
+                    output.item_quantity.0
+                )
+            })
+            .collect();
+        let ingredients: Vec<String> = recipe
+            .ingredients
+            .iter()
+            .map(|ingredient| {
+                let kept = if ingredient.catalyst { " (kept)" } else { "" };
+                format!(
+                    "{} x{}{kept}",
+                    item_name(&item_db, ingredient.item_id),
+                    ingredient.item_quantity.0
+                )
+            })
+            .collect();
+        let cursor = if idx == menu.selected { ">" } else { " " };
+        let station = match recipe.station {
+            Some(kind) => format!("  @ {kind:?}"),
+            None => String::new(),
+        };
+        text.sections[0].value = format!(
+            "{cursor} {}  <  {}{station}",
+            outputs.join(", "),
+            ingredients.join(", ")
+        );
+        text.sections[0].style.color =
+            if can_afford(recipe, player_inv) && station_nearby(recipe, player_pos, &stations_q) {
+                Color::BLACK
+            } else {
+                Color::GRAY
+            };
+    }
+}
+
+//=====> Crafting progress
+#[derive(Component)]
+struct CraftingProgressUi;
+
+#[derive(Component)]
+struct CraftingProgressFill;
+
+#[derive(Component)]
+struct CraftingProgressText;
+
+fn create_crafting_progress_ui(mut commands: Commands, font: Res<FontAssets>) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    position_type: PositionType::Absolute,
+                    position: UiRect {
+                        left: Val::Px(440.),
+                        bottom: Val::Px(84.),
+                        ..default()
+                    },
+                    size: Size::new(Val::Px(200.), Val::Px(12.)),
+                    ..default()
+                },
+                background_color: Color::DARK_GRAY.into(),
+                visibility: Visibility { is_visible: false },
+                ..default()
+            },
+            CraftingProgressUi,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Percent(0.), Val::Percent(100.)),
+                        ..default()
+                    },
+                    background_color: Color::rgb(0.3, 0.8, 0.3).into(),
+                    ..default()
+                },
+                CraftingProgressFill,
+            ));
+        });
+
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.chunk.clone(),
+                font_size: 18.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(440.),
+                bottom: Val::Px(100.),
+                ..default()
+            },
+            ..default()
+        }),
+        CraftingProgressText,
+    ));
+}
+
+// Fills the bar with the player's current job, it stays put while the game is paused
+fn ui_crafting_progress(
+    mut ev_progress: EventReader<CraftingProgressEvent>,
+    player_q: Query<(Entity, &CraftingQueue), With<Player>>,
+    mut bar_q: Query<&mut Visibility, With<CraftingProgressUi>>,
+    mut fill_q: Query<&mut Style, With<CraftingProgressFill>>,
+    mut text_q: Query<&mut Text, With<CraftingProgressText>>,
+    item_db: Res<ItemDatabase>,
+) {
+    let (player, queue) = match player_q.get_single() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    if queue.is_empty() {
+        for mut bar in bar_q.iter_mut() {
+            bar.is_visible = false;
+        }
+        for mut text in text_q.iter_mut() {
+            text.sections[0].value = String::new();
+        }
+        ev_progress.clear();
+        return;
+    }
+
+    if let Some(ev) = ev_progress.iter().rev().find(|ev| ev.who == player) {
+        for mut bar in bar_q.iter_mut() {
+            bar.is_visible = true;
+        }
+        for mut fill in fill_q.iter_mut() {
+            fill.size.width = Val::Percent(ev.progress * 100.);
+        }
+        let queued = match ev.queued {
+            0 => String::new(),
+            n => format!(" (+{n} queued)"),
+        };
+        for mut text in text_q.iter_mut() {
+            text.sections[0].value = format!("crafting {}{queued}", item_name(&item_db, ev.output));
+        }
+    }
+}
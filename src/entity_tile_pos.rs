@@ -12,7 +12,7 @@
 
 /// EntityTilePos
 /// An entity position on the grid
-use crate::world_gen::{TILE_PIXELS_X, TILE_PIXELS_Y};
+use crate::world_gen::tile_to_world;
 
 use bevy::prelude::*;
 use bevy_ecs_tilemap::tiles::TilePos;
@@ -25,7 +25,7 @@ pub struct EntityTilePos {
 
 impl EntityTilePos {
     pub fn center_in_world(&self) -> Vec2 {
-        Vec2::new(TILE_PIXELS_X * self.x as f32, TILE_PIXELS_Y * self.y as f32)
+        tile_to_world(self.x, self.y)
     }
 
     #[allow(dead_code)]
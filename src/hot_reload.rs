@@ -0,0 +1,259 @@
+/// Hot Reload
+///
+/// Watches the worldgen config and the item, recipe and loot json while the game runs and reloads them in place
+/// The files are read straight from disk instead of through the asset server, so they are polled for changes
+/// Broken files are shown on screen and the last good version stays in use until they are fixed
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::prelude::*;
+use iyes_loopless::prelude::*;
+use std::{fs, path::PathBuf, time::SystemTime};
+
+use crate::{
+    assets::FontAssets,
+    comfort_config::{config_path, load_config, ComfortConfig},
+    crafting::{load_recipe_database, RecipeDatabase, RECIPES_FILE},
+    inventory::InventoryUpdate,
+    item_util::{load_item_database, load_loot_tables, Item, ItemDatabase, LootTables, ITEMS_FILE, LOOT_FILE},
+    world_gen::RegenerateWorldEvent,
+    GameState,
+};
+
+const POLL_SECONDS: f32 = 0.5;
+
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WatchedFiles::new())
+            .init_resource::<ReloadErrors>()
+            .add_event::<FileChangedEvent>()
+            .add_enter_system(GameState::GameLoading, create_reload_errors_ui)
+            .add_system(watch_files.run_in_state(GameState::Running).label("watch"))
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Running)
+                    .run_on_event::<FileChangedEvent>()
+                    .after("watch")
+                    .with_system(reload_items)
+                    .with_system(reload_recipes)
+                    .with_system(reload_loot)
+                    .with_system(reload_worldgen)
+                    .into(),
+            )
+            .add_system(show_reload_errors.run_not_in_state(GameState::AssetLoading));
+    }
+}
+
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
+enum WatchedFile {
+    WorldGen,
+    Items,
+    Recipes,
+    Loot,
+}
+
+impl WatchedFile {
+    const ALL: [WatchedFile; 4] = [
+        WatchedFile::WorldGen,
+        WatchedFile::Items,
+        WatchedFile::Recipes,
+        WatchedFile::Loot,
+    ];
+
+    fn path(&self) -> PathBuf {
+        match self {
+            WatchedFile::WorldGen => config_path(),
+            WatchedFile::Items => PathBuf::from(ITEMS_FILE),
+            WatchedFile::Recipes => PathBuf::from(RECIPES_FILE),
+            WatchedFile::Loot => PathBuf::from(LOOT_FILE),
+        }
+    }
+
+    fn modified(&self) -> Option<SystemTime> {
+        fs::metadata(self.path()).and_then(|meta| meta.modified()).ok()
+    }
+}
+
+struct FileChangedEvent(WatchedFile);
+
+#[derive(Resource)]
+struct WatchedFiles {
+    timer: Timer,
+    modified: HashMap<WatchedFile, Option<SystemTime>>,
+    // What the current world was generated from, so saving unrelated settings does not rebuild it
+    worldgen: Option<ComfortConfig>,
+}
+
+impl WatchedFiles {
+    fn new() -> WatchedFiles {
+        WatchedFiles {
+            timer: Timer::from_seconds(POLL_SECONDS, TimerMode::Repeating),
+            modified: WatchedFile::ALL.iter().map(|file| (*file, file.modified())).collect(),
+            worldgen: load_config().ok(),
+        }
+    }
+}
+
+// Last error of every file that failed to reload
+#[derive(Resource, Default)]
+struct ReloadErrors(HashMap<WatchedFile, String>);
+
+impl ReloadErrors {
+    fn report<E: std::fmt::Display>(&mut self, file: WatchedFile, err: E) {
+        let message = format!("Could not reload {}, {err}", file.path().display());
+        println!("{message}");
+        self.0.insert(file, message);
+    }
+}
+
+fn watch_files(mut watched: ResMut<WatchedFiles>, time: Res<Time>, mut ev_changed: EventWriter<FileChangedEvent>) {
+    if !watched.timer.tick(time.delta()).just_finished() {
+        return;
+    }
+
+    for file in WatchedFile::ALL {
+        let modified = file.modified();
+        if watched.modified.insert(file, modified) != Some(modified) {
+            println!("{} changed, reloading", file.path().display());
+            ev_changed.send(FileChangedEvent(file));
+        }
+    }
+}
+
+// Ground items carry a copy of their item, so they get the new name and sprite too
+fn reload_items(
+    mut ev_changed: EventReader<FileChangedEvent>,
+    mut item_db: ResMut<ItemDatabase>,
+    mut errors: ResMut<ReloadErrors>,
+    mut ground_items_q: Query<(&mut Item, &mut TileTextureIndex)>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+) {
+    if !ev_changed.iter().any(|ev| ev.0 == WatchedFile::Items) {
+        return;
+    }
+
+    match load_item_database() {
+        Ok(new_db) => {
+            *item_db = new_db;
+            errors.0.remove(&WatchedFile::Items);
+            for (mut item, mut texture_index) in ground_items_q.iter_mut() {
+                match item_db.items.get(&item.id) {
+                    Some(new_item) => {
+                        *item = new_item.clone();
+                        texture_index.0 = new_item.atlas_index;
+                    }
+                    None => println!("{} ({:?}) is lying around but no longer exists", item.name, item.id),
+                }
+            }
+            ev_invupdate.send(InventoryUpdate);
+            println!("Reloaded {} items", item_db.items.len());
+        }
+        Err(err) => errors.report(WatchedFile::Items, err),
+    }
+}
+
+fn reload_recipes(
+    mut ev_changed: EventReader<FileChangedEvent>,
+    mut recipe_db: ResMut<RecipeDatabase>,
+    mut errors: ResMut<ReloadErrors>,
+) {
+    if !ev_changed.iter().any(|ev| ev.0 == WatchedFile::Recipes) {
+        return;
+    }
+
+    match load_recipe_database() {
+        Ok(new_db) => {
+            *recipe_db = new_db;
+            errors.0.remove(&WatchedFile::Recipes);
+            println!("Reloaded {} recipes", recipe_db.recipes.len());
+        }
+        Err(err) => errors.report(WatchedFile::Recipes, err),
+    }
+}
+
+fn reload_loot(
+    mut ev_changed: EventReader<FileChangedEvent>,
+    mut loot: ResMut<LootTables>,
+    mut errors: ResMut<ReloadErrors>,
+) {
+    if !ev_changed.iter().any(|ev| ev.0 == WatchedFile::Loot) {
+        return;
+    }
+
+    match load_loot_tables() {
+        Ok(new_loot) => {
+            *loot = new_loot;
+            errors.0.remove(&WatchedFile::Loot);
+            println!("Reloaded {} loot tables", loot.tables.len());
+        }
+        Err(err) => errors.report(WatchedFile::Loot, err),
+    }
+}
+
+// The config is only ever read when a world gets built, so all that's left is deciding whether to build one
+fn reload_worldgen(
+    mut ev_changed: EventReader<FileChangedEvent>,
+    mut watched: ResMut<WatchedFiles>,
+    mut errors: ResMut<ReloadErrors>,
+    mut ev_regenerate: EventWriter<RegenerateWorldEvent>,
+) {
+    if !ev_changed.iter().any(|ev| ev.0 == WatchedFile::WorldGen) {
+        return;
+    }
+
+    match load_config() {
+        Ok(config) => {
+            errors.0.remove(&WatchedFile::WorldGen);
+            let unchanged = watched
+                .worldgen
+                .as_ref()
+                .is_some_and(|current| current.generates_like(&config));
+            if config.world.regenerate_on_change && !unchanged {
+                println!("World generation settings changed, regenerating the world");
+                ev_regenerate.send(RegenerateWorldEvent);
+            }
+            watched.worldgen = Some(config);
+        }
+        Err(err) => errors.report(WatchedFile::WorldGen, err),
+    }
+}
+
+//=====> Error display
+#[derive(Component)]
+struct ReloadErrorsUi;
+
+fn create_reload_errors_ui(mut commands: Commands, font: Res<FontAssets>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.chunk.clone(),
+                font_size: 18.0,
+                color: Color::RED,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.),
+                bottom: Val::Px(10.),
+                ..default()
+            },
+            max_size: Size::new(Val::Px(1060.), Val::Undefined),
+            ..default()
+        }),
+        ReloadErrorsUi,
+    ));
+}
+
+fn show_reload_errors(errors: Res<ReloadErrors>, mut errors_ui_q: Query<&mut Text, With<ReloadErrorsUi>>) {
+    if !errors.is_changed() {
+        return;
+    }
+
+    let mut messages: Vec<String> = errors.0.values().cloned().collect();
+    messages.sort();
+    for mut text in errors_ui_q.iter_mut() {
+        text.sections[0].value = messages.join("\n");
+    }
+}
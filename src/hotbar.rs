@@ -0,0 +1,220 @@
+/// Hotbar
+///
+/// The first row of the inventory doubles as the hotbar, the item in the active slot is the one held
+/// Number keys pick a slot, the mouse wheel goes through them and E uses what is held
+use bevy::{input::mouse::MouseWheel, prelude::*};
+use iyes_loopless::prelude::*;
+
+use crate::{
+    assets::FontAssets,
+    building::BuildMode,
+    inventory::{Inventory, InventoryUpdate},
+    item_util::{ItemDatabase, ItemId, ItemQuantity},
+    player::{Player, SystemOrder},
+    GameState,
+};
+
+pub const HOTBAR_SLOTS: usize = 9; // One for every number key
+const SLOT_KEYS: [KeyCode; HOTBAR_SLOTS] = [
+    KeyCode::Key1,
+    KeyCode::Key2,
+    KeyCode::Key3,
+    KeyCode::Key4,
+    KeyCode::Key5,
+    KeyCode::Key6,
+    KeyCode::Key7,
+    KeyCode::Key8,
+    KeyCode::Key9,
+];
+const SLOT_SIZE: f32 = 64.;
+const SLOT_COLOR: Color = Color::rgba(0.1, 0.1, 0.1, 0.6);
+const ACTIVE_SLOT_COLOR: Color = Color::rgba(0.8, 0.6, 0.3, 0.8);
+
+pub struct HotbarPlugin;
+
+impl Plugin for HotbarPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_enter_system(GameState::GameLoading, create_hotbar_ui)
+            .add_system_set(
+                ConditionSet::new()
+                    .run_in_state(GameState::Running)
+                    .label(SystemOrder::Input)
+                    .before(SystemOrder::Logic)
+                    .with_system(select_hotbar_slot)
+                    .with_system(use_held_item)
+                    .into(),
+            )
+            .add_system(ui_hotbar_update.run_not_in_state(GameState::AssetLoading));
+    }
+}
+
+/// The hotbar slot the player holds an item from
+#[derive(Component, Default)]
+pub struct ActiveSlot(pub usize);
+
+impl ActiveSlot {
+    /// The item in the active slot, None is bare hands
+    pub fn held(&self, inventory: &Inventory) -> Option<ItemId> {
+        inventory.slot(self.0).map(|(id, _)| id)
+    }
+}
+
+fn print_held_item(held: Option<ItemId>, item_db: &ItemDatabase) {
+    match held.and_then(|id| item_db.items.get(&id)) {
+        Some(item) => println!("holding {}", item.name),
+        None => println!("holding nothing"),
+    }
+}
+
+// 1 to 9 pick a slot straight away and the wheel steps through them
+// Holding Ctrl leaves the wheel to the camera zoom
+fn select_hotbar_slot(
+    mut player_q: Query<(&Inventory, &mut ActiveSlot), With<Player>>,
+    item_db: Res<ItemDatabase>,
+    mut scroll_wheel: EventReader<MouseWheel>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    let (inventory, mut active) = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in hotbar fn"),
+    };
+
+    let mut selected = active.0;
+    if let Some(slot) = SLOT_KEYS.iter().position(|key| keeb.just_pressed(*key)) {
+        selected = slot;
+    }
+    let zooming = keeb.pressed(KeyCode::LControl) || keeb.pressed(KeyCode::RControl);
+    for wheel in scroll_wheel.iter() {
+        if zooming {
+            continue;
+        }
+        if wheel.y < 0.0 {
+            selected = (selected + 1) % HOTBAR_SLOTS;
+        } else if wheel.y > 0.0 {
+            selected = (selected + HOTBAR_SLOTS - 1) % HOTBAR_SLOTS;
+        }
+    }
+
+    if selected != active.0 {
+        active.0 = selected;
+        print_held_item(active.held(inventory), &item_db);
+    }
+}
+
+// E uses the held item, food is eaten and placeable items start build mode
+fn use_held_item(
+    mut player_q: Query<(&ActiveSlot, &mut Inventory), With<Player>>,
+    mut build_mode: ResMut<BuildMode>,
+    item_db: Res<ItemDatabase>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::E) {
+        return;
+    }
+
+    let (active, mut inventory) = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in hotbar fn"),
+    };
+    let item = match active.held(&inventory).and_then(|id| item_db.items.get(&id)) {
+        Some(item) => item,
+        None => return,
+    };
+
+    if item.consumable {
+        inventory.remove_from_slot(active.0, &ItemQuantity(1));
+        ev_invupdate.send(InventoryUpdate);
+        println!("ate a {}", item.name);
+    } else if item.placeable.is_some() {
+        build_mode.0 = true;
+        println!("building with {}", item.name);
+    } else {
+        println!("{} can not be used", item.name);
+    }
+}
+
+#[derive(Component)]
+struct HotbarSlot(usize);
+
+#[derive(Component)]
+struct HotbarSlotText(usize);
+
+fn create_hotbar_ui(mut commands: Commands, font: Res<FontAssets>) {
+    let text_style = TextStyle {
+        font: font.chunk.clone(),
+        font_size: 14.0,
+        color: Color::WHITE,
+    };
+
+    commands
+        .spawn(NodeBundle {
+            style: Style {
+                position_type: PositionType::Absolute,
+                position: UiRect {
+                    bottom: Val::Px(8.),
+                    ..default()
+                },
+                size: Size::new(Val::Percent(100.), Val::Px(SLOT_SIZE)),
+                justify_content: JustifyContent::Center,
+                ..default()
+            },
+            ..default()
+        })
+        .with_children(|parent| {
+            for i in 0..HOTBAR_SLOTS {
+                parent
+                    .spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Px(SLOT_SIZE), Val::Px(SLOT_SIZE)),
+                                margin: UiRect::all(Val::Px(2.)),
+                                padding: UiRect::all(Val::Px(4.)),
+                                ..default()
+                            },
+                            background_color: SLOT_COLOR.into(),
+                            ..default()
+                        },
+                        HotbarSlot(i),
+                    ))
+                    .with_children(|parent| {
+                        parent.spawn((
+                            TextBundle::from_section(format!("{}", i + 1), text_style.clone()),
+                            HotbarSlotText(i),
+                        ));
+                    });
+            }
+        });
+}
+
+// Redraws the hotbar whenever what is held or the slots themselves change
+#[allow(clippy::type_complexity)]
+fn ui_hotbar_update(
+    player_q: Query<(&Inventory, &ActiveSlot), (With<Player>, Or<(Changed<Inventory>, Changed<ActiveSlot>)>)>,
+    mut ui_slots_q: Query<(&mut BackgroundColor, &HotbarSlot)>,
+    mut ui_text_q: Query<(&mut Text, &HotbarSlotText)>,
+    item_db: Res<ItemDatabase>,
+) {
+    let (inventory, active) = match player_q.get_single() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    for (mut color, slot) in ui_slots_q.iter_mut() {
+        *color = if slot.0 == active.0 {
+            ACTIVE_SLOT_COLOR.into()
+        } else {
+            SLOT_COLOR.into()
+        };
+    }
+
+    for (mut text, slot) in ui_text_q.iter_mut() {
+        text.sections[0].value = match inventory.slot(slot.0) {
+            Some((id, amt)) => {
+                let name = item_db.items.get(&id).map_or("undefined", |item| item.name.as_str());
+                format!("{}\n{name}\nx{}", slot.0 + 1, amt.0)
+            }
+            None => format!("{}", slot.0 + 1),
+        };
+    }
+}
@@ -1,11 +1,14 @@
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 use iyes_loopless::prelude::*;
+use serde::Deserialize;
 
 use crate::{
-    item_util::{ItemId, SpawnItemEvent},
+    hotbar::ActiveSlot,
+    inventory::Inventory,
+    item_util::{ItemDatabase, LootTables, SpawnItemEvent, Tool, ToolKind},
     player::SystemOrder,
-    world_gen::{Blocking, ObjectSize},
+    world_gen::{despawn_obj, Footprint, WorldTilePos, WorldTilesMut},
     GameState,
 };
 
@@ -36,7 +39,27 @@ pub enum Interact {
 
 pub struct HealthBelowZeroEvent(pub Entity, pub TilePos);
 
-#[derive(Component)]
+const FIST_DAMAGE: i32 = 1;
+
+/// The tool a world obj is meant to be harvested with, anything else only does as much as a fist
+#[derive(Component, Clone, Copy)]
+pub struct ToolRequirement {
+    pub kind: ToolKind,
+    pub strict: bool, // when set nothing but the right tool does any damage
+}
+
+/// What a world obj is, picks the loot table it drops from once it is destroyed
+#[derive(Component, Deserialize, Debug, Clone, Copy, Hash, Eq, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ObjectKind {
+    Tree,
+    Rock,
+    Mound,
+    Flower,
+    Mushroom,
+}
+
+#[derive(Component, Clone, Copy)]
 pub struct Health {
     pub max_hp: u32,
     pub hp: i32,
@@ -51,33 +74,21 @@ impl Health {
     }
 }
 
-// Removes dead world objs that are sent via the HealthBelowZeroEvent
+// Removes dead world objs that are sent via the HealthBelowZeroEvent, multi tile objs go with all of their tiles
 fn cleanup_world_objs(
     mut commands: Commands,
     mut ev_killed: EventReader<HealthBelowZeroEvent>,
-    mut tile_storage_q: Query<&mut TileStorage, With<Blocking>>,
-    world_objs_q: Query<(Entity, &ObjectSize, &TilePos)>,
+    mut world_tiles: WorldTilesMut,
+    footprints_q: Query<&Footprint>,
 ) {
     for ev in ev_killed.iter() {
-        for (obj, obj_size, obj_pos) in world_objs_q.iter() {
-            match obj_size {
-                ObjectSize::Single => {
-                    for mut tile_storage in tile_storage_q.iter_mut() {
-                        tile_storage.remove(&ev.1);
-                    }
-                    commands.entity(ev.0).despawn_recursive();
-                }
-                ObjectSize::Multi(owner) => {
-                    if *owner == ev.0 {
-                        // will remove the owner and the tiles associated with the owner
-                        for mut tile_storage in tile_storage_q.iter_mut() {
-                            tile_storage.remove(obj_pos);
-                        }
-                        commands.entity(obj).despawn_recursive();
-                    }
-                }
-            }
-        }
+        despawn_obj(
+            &mut commands,
+            &mut world_tiles,
+            ev.0,
+            &ev.1,
+            footprints_q.get(ev.0).ok(),
+        );
     }
 }
 
@@ -87,24 +98,75 @@ pub struct HarvestInteraction {
     pub reciever_pos: TilePos,
 }
 
+#[allow(clippy::type_complexity)]
 fn harvest_interact_handler(
-    mut interactables_q: Query<(Entity, &Interact, &mut Health, &TilePos)>,
+    mut interactables_q: Query<(
+        Entity,
+        &Interact,
+        &mut Health,
+        &WorldTilePos,
+        Option<&ToolRequirement>,
+        Option<&ObjectKind>,
+    )>,
+    harvester_q: Query<(&ActiveSlot, &Inventory)>,
+    item_db: Res<ItemDatabase>,
+    loot: Res<LootTables>,
     mut ev_harvest: EventReader<HarvestInteraction>,
     mut ev_destroyed: EventWriter<HealthBelowZeroEvent>,
     mut ev_spawnitem: EventWriter<SpawnItemEvent>,
 ) {
     for ev in ev_harvest.iter() {
-        if let Ok((interactable, _, mut health, pos)) = interactables_q.get_mut(ev.harvested) {
+        if let Ok((interactable, _, mut health, pos, requirement, kind)) = interactables_q.get_mut(ev.harvested) {
             if health.hp <= 0 {
                 return;
             }
-            health.hp -= 2;
-            println!("struck obj with fist hp: {}", health.hp);
+
+            // Whatever is held only helps when it is a tool
+            let tool = harvester_q
+                .get(ev.harvester)
+                .ok()
+                .and_then(|(active, inventory)| active.held(inventory))
+                .and_then(|id| item_db.items.get(&id))
+                .and_then(|item| item.tool.map(|tool| (item.name.as_str(), tool)));
+
+            let damage = harvest_damage(tool.map(|(_, tool)| tool), requirement);
+            if damage <= 0 {
+                if let Some(requirement) = requirement {
+                    println!("obj can only be harvested with a {:?}", requirement.kind);
+                }
+                continue;
+            }
+            health.hp -= damage;
+            println!(
+                "struck obj with {} hp: {}",
+                tool.map_or("fist", |(name, _)| name),
+                health.hp
+            );
             if health.hp <= 0 {
-                ev_destroyed.send(HealthBelowZeroEvent(interactable, *pos));
-                ev_spawnitem.send(SpawnItemEvent::from(ev.reciever_pos.x, ev.reciever_pos.y, ItemId(1)));
+                ev_destroyed.send(HealthBelowZeroEvent(interactable, pos.0));
+                // Loot lands where the obj was struck from
+                if let Some(table) = kind.and_then(|kind| loot.tables.get(kind)) {
+                    for (item_id, qty) in table.roll(&mut rand::thread_rng()) {
+                        ev_spawnitem.send(SpawnItemEvent::with_quantity(
+                            ev.reciever_pos.x,
+                            ev.reciever_pos.y,
+                            item_id,
+                            qty,
+                        ));
+                    }
+                }
                 println!("obj is dead");
             }
         }
     }
 }
+
+// The right tool does its full damage, the wrong one or none at all is as good as a fist unless the obj is strict
+fn harvest_damage(tool: Option<Tool>, requirement: Option<&ToolRequirement>) -> i32 {
+    match (tool, requirement) {
+        (Some(tool), Some(requirement)) if tool.kind == requirement.kind => tool.damage,
+        (_, Some(requirement)) if requirement.strict => 0,
+        (Some(tool), None) => tool.damage,
+        _ => FIST_DAMAGE,
+    }
+}
@@ -1,13 +1,17 @@
+use std::cmp::Ordering;
+
 use bevy::{prelude::*, ui::widget::ImageMode};
 use bevy_ecs_tilemap::prelude::*;
 use iyes_loopless::prelude::*;
 
 use crate::{
     assets::{FontAssets, UiAssets},
+    building::BuildMode,
     entity_tile_pos::EntityTilePos,
-    item_util::{Item, ItemDatabase, ItemId, ItemQuantity},
+    hotbar::{ActiveSlot, HOTBAR_SLOTS},
+    item_util::{Item, ItemCategory, ItemDatabase, ItemId, ItemQuantity, SpawnItemEvent},
     player::{Direction, Player, SystemOrder},
-    world_gen::ItemStorage,
+    world_gen::{Layer, WorldTilesMut},
     GameState,
 };
 
@@ -26,33 +30,41 @@ impl Plugin for InventoryPlugin {
                     .into(),
             )
             .add_event::<InventoryUpdate>()
+            .add_event::<InventoryActionEvent>()
             .add_system(
                 ui_inventory_update
-                    .run_in_state(GameState::Running)
+                    .run_not_in_state(GameState::AssetLoading)
                     .run_on_event::<InventoryUpdate>(),
             )
+            .add_system(toggle_inventory.run_in_state(GameState::Menu))
             .add_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::Menu)
-                    .with_system(toggle_inventory)
+                    .run_if_resource_exists::<InventoryMenu>()
                     .with_system(move_inventory_cursor)
+                    .with_system(select_inventory_slot)
                     .into(),
+            )
+            .add_system(
+                handle_inventory_action
+                    .run_in_state(GameState::Menu)
+                    .run_if_resource_exists::<InventoryMenu>()
+                    .run_on_event::<InventoryActionEvent>(),
             );
     }
 }
 
-/// The max capacity of items able to be held
-#[derive(Component)]
-struct InventoryCapacity(u32);
+pub const INVENTORY_SLOTS: usize = 36; // Four rows of the hotbar, the first row is the hotbar itself
+const INVENTORY_LINES: usize = 18; // Slots on a single page of the inventory ui
 
-// u32 is the id of the item
+/// Items held in fixed slots, every slot holds a single stack no bigger than the item's max stack
 #[derive(Component)]
 pub struct Inventory {
-    items: Vec<InventoryItem>,
-    // items: HashMap<ItemId, ItemQuantity>,
-    max_size: usize,
+    slots: Vec<Option<InventoryItem>>,
 }
 
+// u32 is the id of the item
+#[derive(Clone, Copy)]
 struct InventoryItem {
     id: u32,
     amt: u32,
@@ -60,61 +72,207 @@ struct InventoryItem {
 
 impl Inventory {
     pub fn new() -> Self {
+        Inventory::with_slots(INVENTORY_SLOTS)
+    }
+
+    pub fn with_slots(slots: usize) -> Self {
         Inventory {
-            // items: HashMap::new(),
-            items: Vec::new(),
-            max_size: 15,
+            slots: vec![None; slots],
         }
     }
 
-    pub fn add_item(&mut self, id: ItemId, amt: &ItemQuantity) {
-        match self.items.iter().position(|i| i.id == id.0) {
-            Some(idx) => { self.items[idx].amt += amt.0;},
-            None => if self.items.len() < self.max_size {
-                self.items.push(InventoryItem { id: id.0, amt: amt.0});
+    // Tops up the stacks already held before starting new ones in empty slots
+    // Returns how much did not fit, the caller decides what happens to it
+    pub fn add_item(&mut self, id: ItemId, amt: &ItemQuantity, item_db: &ItemDatabase) -> ItemQuantity {
+        let max_stack = item_db.max_stack(id);
+        let mut left = amt.0;
+        for item in self.slots.iter_mut().flatten().filter(|item| item.id == id.0) {
+            let moved = left.min(max_stack.saturating_sub(item.amt));
+            item.amt += moved;
+            left -= moved;
+        }
+        for slot in self.slots.iter_mut().filter(|slot| slot.is_none()) {
+            if left == 0 {
+                break;
             }
+            let moved = left.min(max_stack);
+            *slot = Some(InventoryItem { id: id.0, amt: moved });
+            left -= moved;
         }
+        ItemQuantity(left)
     }
 
-    // Attempts to remove items from an inventory
+    // Attempts to remove items from an inventory, the stacks in the last slots go first
     // Will fail if the quantity in the inventory is less than what is trying to be removed
     pub fn remove_item(&mut self, id: ItemId, amt: &ItemQuantity) -> bool {
-        match self.items.iter().position(|i| i.id == id.0) {
-            Some(idx) => { 
-                if self.items[idx].amt >= amt.0 {
-                    self.items[idx].amt -= amt.0;
-                    if self.items[idx].amt == 0 {
-                        self.items.remove(idx);
+        if !self.contains_item(id, amt) {
+            return false;
+        }
+
+        let mut left = amt.0;
+        for slot in self.slots.iter_mut().rev() {
+            if let Some(item) = slot {
+                if item.id == id.0 && left > 0 {
+                    let taken = left.min(item.amt);
+                    item.amt -= taken;
+                    left -= taken;
+                    if item.amt == 0 {
+                        *slot = None;
                     }
-                    return true;
-                }   
-                return false; 
-            },
-            None => false
+                }
+            }
         }
+        true
+    }
 
+    // What every slot holds, None for the empty ones
+    pub fn slots(&self) -> impl Iterator<Item = Option<(ItemId, ItemQuantity)>> + '_ {
+        self.slots
+            .iter()
+            .map(|slot| slot.map(|item| (ItemId(item.id), ItemQuantity(item.amt))))
+    }
 
-        // match self.items.entry(id) {
-        //     Entry::Occupied(mut o) => {
-        //         if o.get().0 >= amt.0 {
-        //             o.get_mut().0 -= amt.0;
-        //             if o.get().0 == 0 {
-        //                 o.remove_entry();
-        //             }
-        //             return true;
-        //         }
-        //         false
-        //     }
-        //     Entry::Vacant(_v) => false,
-        // }
+    // What a single slot holds, None when it is empty
+    pub fn slot(&self, idx: usize) -> Option<(ItemId, ItemQuantity)> {
+        self.slots().nth(idx).flatten()
+    }
+
+    // Empties the slot and hands back what was in it
+    pub fn take_slot(&mut self, idx: usize) -> Option<(ItemId, ItemQuantity)> {
+        self.slots
+            .get_mut(idx)
+            .and_then(|slot| slot.take())
+            .map(|item| (ItemId(item.id), ItemQuantity(item.amt)))
+    }
+
+    // Moves half of the stack into the first empty slot
+    // Will fail if there is only one of the item or no empty slot to put the other half in
+    pub fn split_slot(&mut self, idx: usize) -> bool {
+        let item = match self.slots.get(idx).copied().flatten() {
+            Some(item) if item.amt > 1 => item,
+            _ => return false,
+        };
+        let empty = match self.slots.iter().position(|slot| slot.is_none()) {
+            Some(empty) => empty,
+            None => return false,
+        };
+
+        let half = item.amt / 2;
+        self.slots[idx] = Some(InventoryItem {
+            id: item.id,
+            amt: item.amt - half,
+        });
+        self.slots[empty] = Some(InventoryItem { id: item.id, amt: half });
+        true
+    }
+
+    // Takes from a single stack, will fail if the slot holds less than that
+    pub fn remove_from_slot(&mut self, idx: usize, amt: &ItemQuantity) -> bool {
+        let slot = match self.slots.get_mut(idx) {
+            Some(slot) => slot,
+            None => return false,
+        };
+        match slot {
+            Some(item) if item.amt >= amt.0 => {
+                item.amt -= amt.0;
+                if item.amt == 0 {
+                    *slot = None;
+                }
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Moves as much of the stack as fits into the other inventory, the rest stays in its slot
+    // Returns how many were moved
+    pub fn transfer_slot(&mut self, idx: usize, to: &mut Inventory, item_db: &ItemDatabase) -> u32 {
+        let item = match self.slots.get(idx).copied().flatten() {
+            Some(item) => item,
+            None => return 0,
+        };
+        let left = to.add_item(ItemId(item.id), &ItemQuantity(item.amt), item_db);
+        self.slots[idx] = match left.0 {
+            0 => None,
+            amt => Some(InventoryItem { id: item.id, amt }),
+        };
+        item.amt - left.0
+    }
+
+    pub fn swap_slots(&mut self, a: usize, b: usize) {
+        if a < self.slots.len() && b < self.slots.len() {
+            self.slots.swap(a, b);
+        }
+    }
+
+    // Tops up the earlier stacks of every item from its later ones so it takes up as few slots as it can
+    pub fn merge_stacks(&mut self, item_db: &ItemDatabase) {
+        for i in 0..self.slots.len() {
+            let mut item = match self.slots[i] {
+                Some(item) => item,
+                None => continue,
+            };
+            let max_stack = item_db.max_stack(ItemId(item.id));
+            for slot in self.slots.iter_mut().skip(i + 1) {
+                if let Some(other) = slot {
+                    if other.id == item.id {
+                        let moved = other.amt.min(max_stack.saturating_sub(item.amt));
+                        item.amt += moved;
+                        other.amt -= moved;
+                        if other.amt == 0 {
+                            *slot = None;
+                        }
+                    }
+                }
+            }
+            self.slots[i] = Some(item);
+        }
+    }
+
+    // Merges the stacks and then sorts the slots past the hotbar, empty slots end up last
+    // The hotbar is left how the player set it up
+    pub fn sort(&mut self, key: SortKey, item_db: &ItemDatabase) {
+        self.merge_stacks(item_db);
+
+        let name = |item: &InventoryItem| {
+            item_db
+                .items
+                .get(&ItemId(item.id))
+                .map_or("", |info| info.name.as_str())
+        };
+        let category = |item: &InventoryItem| item_db.items.get(&ItemId(item.id)).map(|info| info.category);
+        self.slots[HOTBAR_SLOTS..].sort_by(|a, b| match (a, b) {
+            (Some(a), Some(b)) => match key {
+                SortKey::Name => name(a).cmp(name(b)),
+                SortKey::Id => a.id.cmp(&b.id),
+                SortKey::Category => category(a).cmp(&category(b)).then_with(|| name(a).cmp(name(b))),
+                SortKey::Quantity => b.amt.cmp(&a.amt).then_with(|| name(a).cmp(name(b))),
+            }
+            .then_with(|| a.id.cmp(&b.id))
+            .then_with(|| b.amt.cmp(&a.amt)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        });
+    }
+
+    pub fn clear(&mut self) {
+        self.slots.iter_mut().for_each(|slot| *slot = None);
+    }
+
+    // How many of the item are held across all of its stacks
+    pub fn count(&self, id: ItemId) -> u32 {
+        self.slots
+            .iter()
+            .flatten()
+            .filter(|item| item.id == id.0)
+            .map(|item| item.amt)
+            .sum()
     }
 
     // Checks the inventory to see if there is the specified quantity and item inside
     pub fn contains_item(&self, id: ItemId, amt: &ItemQuantity) -> bool {
-        match self.items.iter().position(|i| i.id == id.0) {
-            Some(idx) => self.items[idx].amt >= amt.0,
-            None => false,
-        }
+        self.count(id) >= amt.0
     }
 }
 
@@ -123,9 +281,10 @@ impl Inventory {
 fn take_item(
     mut commands: Commands,
     mut player_q: Query<(&EntityTilePos, &Direction, &mut Inventory), With<Player>>,
-    mut tilestorage_q: Query<&mut TileStorage, With<ItemStorage>>,
+    mut world_tiles: WorldTilesMut,
     mut ev_invopen: EventWriter<InventoryUpdate>,
-    items_q: Query<(Entity, &Item, &ItemQuantity), With<TilePos>>,
+    mut items_q: Query<(Entity, &Item, &mut ItemQuantity), With<TilePos>>,
+    item_db: Res<ItemDatabase>,
     keeb: Res<Input<KeyCode>>,
 ) {
     // T for Take, this action may be held
@@ -133,25 +292,12 @@ fn take_item(
         return;
     }
 
-    let mut tile_storage = match tilestorage_q.get_single_mut() {
-        Ok(t) => t,
-        Err(_) => panic!("found more than one itemstorage"),
-    };
     let (position, direction, mut inventory) = match player_q.get_single_mut() {
         Ok(e) => e,
         Err(_) => panic!("found more than one player in harvest fn"),
     };
 
-    let dest_tile = TilePos::new(position.x, position.y);
-
-    if let Some(tile_entity) = tile_storage.get(&dest_tile) {
-        if let Ok((entity, item_info, qty)) = items_q.get(tile_entity) {
-            tile_storage.remove(&dest_tile);
-            commands.entity(entity).despawn_recursive();
-            inventory.add_item(item_info.id, qty);
-        }
-    }
-
+    let under_tile = TilePos::new(position.x, position.y);
     let dest_tile = match *direction {
         Direction::Up => TilePos::new(position.x, position.y + 1),
         Direction::Down => TilePos::new(position.x, position.y - 1),
@@ -159,12 +305,22 @@ fn take_item(
         Direction::Right => TilePos::new(position.x + 1, position.y),
     };
 
-    if let Some(tile_entity) = tile_storage.get(&dest_tile) {
-        if let Ok((entity, item_info, qty)) = items_q.get(tile_entity) {
-            tile_storage.remove(&dest_tile);
-            commands.entity(entity).despawn_recursive();
-            inventory.add_item(item_info.id, qty);
-            ev_invopen.send(InventoryUpdate);
+    for tile_pos in [under_tile, dest_tile] {
+        if let Some(tile_entity) = world_tiles.get(Layer::Items, &tile_pos) {
+            if let Ok((entity, item_info, mut qty)) = items_q.get_mut(tile_entity) {
+                let left = inventory.add_item(item_info.id, &qty, &item_db);
+                if left.0 == qty.0 {
+                    continue;
+                }
+                // Whatever did not fit stays on the ground
+                if left.0 == 0 {
+                    world_tiles.remove(Layer::Items, &tile_pos);
+                    commands.entity(entity).despawn_recursive();
+                } else {
+                    *qty = left;
+                }
+                ev_invopen.send(InventoryUpdate);
+            }
         }
     }
 }
@@ -172,11 +328,122 @@ fn take_item(
 #[derive(Component)]
 struct InventoryUi;
 
+// A line on the current page of the inventory ui
 #[derive(Component)]
-struct InventorySlot(u32);
+struct InventoryLine(usize);
 
 #[derive(Component)]
-struct InventoryPointer(usize);
+struct InventoryHeader;
+
+// One of the actions offered for the item pointed at, left to right
+#[derive(Component)]
+struct InventoryActionSlot(usize);
+
+#[derive(Component)]
+struct InventoryInfo;
+
+const ACTION_SLOTS: usize = 5; // Enough for every action at once
+const POINTER_COLOR: Color = Color::rgb(0.6, 0.15, 0.1);
+
+/// Only exists while the inventory is open, tracks the slot pointed at and the action picked for it
+#[derive(Resource, Default)]
+struct InventoryMenu {
+    pointer: usize,               // position in the slots shown, not the slot itself
+    action: Option<usize>,        // Some while the actions for the slot are open
+    info: String,                 // what the last action did, or the description of an inspected item
+    filter: Option<ItemCategory>, // only items of the category are shown when set
+    sort: Option<SortKey>,        // what the inventory was sorted by last
+}
+
+/// What the slots past the hotbar can be sorted by
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SortKey {
+    Name,
+    Id,
+    Category,
+    Quantity,
+}
+
+impl SortKey {
+    fn next(self) -> SortKey {
+        match self {
+            SortKey::Name => SortKey::Id,
+            SortKey::Id => SortKey::Category,
+            SortKey::Category => SortKey::Quantity,
+            SortKey::Quantity => SortKey::Name,
+        }
+    }
+}
+
+// F goes through these in order
+const FILTERS: [Option<ItemCategory>; 5] = [
+    None,
+    Some(ItemCategory::Resource),
+    Some(ItemCategory::Tool),
+    Some(ItemCategory::Placeable),
+    Some(ItemCategory::Food),
+];
+
+fn filter_label(filter: Option<ItemCategory>) -> String {
+    match filter {
+        Some(category) => format!("{category:?}").to_lowercase(),
+        None => "all".to_string(),
+    }
+}
+
+// Every slot without a filter, otherwise only the slots holding an item of the category
+fn visible_slots(inventory: &Inventory, filter: Option<ItemCategory>, item_db: &ItemDatabase) -> Vec<usize> {
+    (0..INVENTORY_SLOTS)
+        .filter(|idx| match filter {
+            Some(category) => inventory
+                .slot(*idx)
+                .and_then(|(id, _)| item_db.items.get(&id))
+                .is_some_and(|item| item.category == category),
+            None => true,
+        })
+        .collect()
+}
+
+/// What can be done with the item in a slot
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum ItemAction {
+    Use,
+    Equip,
+    Split,
+    Drop,
+    Inspect,
+}
+
+// Only the actions that make sense for the item are offered
+fn item_actions(item: Option<&Item>, amt: &ItemQuantity) -> Vec<ItemAction> {
+    let mut actions = Vec::new();
+    if item.is_some_and(|item| item.consumable || item.placeable.is_some()) {
+        actions.push(ItemAction::Use);
+    }
+    if item.is_some_and(|item| item.tool.is_some()) {
+        actions.push(ItemAction::Equip);
+    }
+    if amt.0 > 1 {
+        actions.push(ItemAction::Split);
+    }
+    actions.push(ItemAction::Drop);
+    actions.push(ItemAction::Inspect);
+    actions
+}
+
+// Actions offered for whatever the slot holds, nothing for an empty slot
+fn slot_actions(inventory: &Inventory, idx: usize, item_db: &ItemDatabase) -> Vec<ItemAction> {
+    match inventory.slot(idx) {
+        Some((id, amt)) => item_actions(item_db.items.get(&id), &amt),
+        None => Vec::new(),
+    }
+}
+
+/// Sent when an action is picked for the item in a slot
+struct InventoryActionEvent {
+    slot: usize,
+    action: ItemAction,
+}
 
 fn create_inventory_ui(mut commands: Commands, font: Res<FontAssets>, elements: Res<UiAssets>) {
     let text_style = TextStyle {
@@ -192,7 +459,7 @@ fn create_inventory_ui(mut commands: Commands, font: Res<FontAssets>, elements:
         ..default()
     };
 
-    let placeholder = format!("{: <40}AMT:{:>3}", "Wood", 999); 
+    let placeholder = format!("   {: <40}AMT:{:>3}", "Wood", 999);
 
     commands
         .spawn((
@@ -213,8 +480,28 @@ fn create_inventory_ui(mut commands: Commands, font: Res<FontAssets>, elements:
                     ..default()
                 })
                 .with_children(|parent| {
-                    // Empty slots for items
-                    for i in 0..18 {
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 20.0,
+                                ..text_style.clone()
+                            },
+                        )
+                        .with_style(Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(50.),
+                                top: Val::Px(22.),
+                                ..default()
+                            },
+                            ..default()
+                        }),
+                        InventoryHeader,
+                    ));
+
+                    // Empty lines for the slots on a page
+                    for i in 0..INVENTORY_LINES {
                         let offset: f32 = i as f32 * 25.0;
                         parent.spawn((
                             TextBundle::from_section(placeholder.clone(), text_style.clone()).with_style(Style {
@@ -227,47 +514,171 @@ fn create_inventory_ui(mut commands: Commands, font: Res<FontAssets>, elements:
                                 },
                                 ..default()
                             }),
-                            InventorySlot(i),
+                            InventoryLine(i),
+                            Interaction::default(),
+                        ));
+                    }
+
+                    // Actions for the item pointed at, empty until they are opened
+                    for i in 0..ACTION_SLOTS {
+                        let offset: f32 = i as f32 * 105.0;
+                        parent.spawn((
+                            TextBundle::from_section("", text_style.clone()).with_style(Style {
+                                position_type: PositionType::Absolute,
+                                position: UiRect {
+                                    left: Val::Px(50. + offset),
+                                    top: Val::Px(505.),
+                                    ..default()
+                                },
+                                ..default()
+                            }),
+                            InventoryActionSlot(i),
+                            Interaction::default(),
                         ));
                     }
+
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 20.0,
+                                ..text_style.clone()
+                            },
+                        )
+                        .with_style(Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                left: Val::Px(50.),
+                                top: Val::Px(545.),
+                                ..default()
+                            },
+                            ..default()
+                        }),
+                        InventoryInfo,
+                    ));
                 });
-                // .with_children(|parent| {
-                //     parent.spawn(ImageBundle {
-                //         image: UiImage(elements.)
-                //     }),
-                //     InventoryPointer(0)
-                // });
         });
 }
 
 pub struct InventoryUpdate;
 
+fn action_label(action: ItemAction, held: bool) -> &'static str {
+    match action {
+        ItemAction::Use => "Use",
+        ItemAction::Equip if held => "Unequip",
+        ItemAction::Equip => "Equip",
+        ItemAction::Split => "Split",
+        ItemAction::Drop => "Drop",
+        ItemAction::Inspect => "Inspect",
+    }
+}
+
+#[allow(clippy::type_complexity)]
 fn ui_inventory_update(
     mut ev_invopen: EventReader<InventoryUpdate>,
-    mut ui_slots_q: Query<&mut Text, With<InventorySlot>>,
-    inv_q: Query<&Inventory, With<Player>>,
+    mut ui_lines_q: Query<(&mut Text, &InventoryLine)>,
+    mut ui_actions_q: Query<(&mut Text, &InventoryActionSlot), Without<InventoryLine>>,
+    mut ui_info_q: Query<
+        (&mut Text, Option<&InventoryHeader>),
+        (
+            Or<(With<InventoryInfo>, With<InventoryHeader>)>,
+            Without<InventoryLine>,
+            Without<InventoryActionSlot>,
+        ),
+    >,
+    inv_q: Query<(&Inventory, &ActiveSlot), With<Player>>,
+    menu: Option<Res<InventoryMenu>>,
     item_db: Res<ItemDatabase>,
 ) {
-    for _ in ev_invopen.iter() {
-        let player_inv = match inv_q.get_single() {
-            Ok(e) => e,
-            Err(_) => panic!("Could not fetch the player's inventory!!!"),
-        };
+    // Any number of updates in a frame only need a single redraw
+    if ev_invopen.iter().count() == 0 {
+        return;
+    }
 
-        let mut filled_in: usize = 0;
-        for (mut text, InventoryItem{id, amt}) in ui_slots_q.iter_mut().zip(player_inv.items.iter()) {
-            if let Some(info) = item_db.items.get(&ItemId(*id)) {
-                text.sections[0].value = format!("{: <40}AMT:{:>3}", info.name, amt);
-            } else {
-                text.sections[0].value = format!("{: <20}AMT:{:>3}", "undefined", amt);
+    let (player_inv, active) = match inv_q.get_single() {
+        Ok(e) => e,
+        Err(_) => panic!("Could not fetch the player's inventory!!!"),
+    };
+    let filter = menu.as_ref().and_then(|menu| menu.filter);
+    let view = visible_slots(player_inv, filter, &item_db);
+    let pointer = menu.as_ref().map(|menu| menu.pointer.min(view.len().saturating_sub(1)));
+    let page = pointer.unwrap_or(0) / INVENTORY_LINES;
+    let pages = view.len().div_ceil(INVENTORY_LINES).max(1);
+    let pointed_slot = pointer.and_then(|pointer| view.get(pointer).copied());
+
+    // Every line shows the slot it belongs to, so items keep their place
+    // The hotbar slots are marked with the key that picks them
+    for (mut text, line) in ui_lines_q.iter_mut() {
+        let position = page * INVENTORY_LINES + line.0;
+        let slot = match view.get(position) {
+            Some(slot) => *slot,
+            None => {
+                text.sections[0].value = String::new();
+                continue;
             }
+        };
+        let cursor = if pointer == Some(position) { ">" } else { " " };
+        let key = if slot < HOTBAR_SLOTS {
+            format!("{}", slot + 1)
+        } else {
+            " ".to_string()
+        };
+        text.sections[0].value = match player_inv.slot(slot) {
+            Some((id, amt)) => match item_db.items.get(&id) {
+                Some(info) if slot == active.0 => {
+                    format!("{cursor}{key} {: <40}AMT:{:>3}", format!("{} (held)", info.name), amt.0)
+                }
+                Some(info) => format!("{cursor}{key} {: <40}AMT:{:>3}", info.name, amt.0),
+                None => format!("{cursor}{key} {: <20}AMT:{:>3}", "undefined", amt.0),
+            },
+            None => format!("{cursor}{key}"),
+        };
+        text.sections[0].style.color = if pointer == Some(position) {
+            POINTER_COLOR
+        } else {
+            Color::BLACK
+        };
+    }
 
-            filled_in += 1;
-        }
-        for mut text in ui_slots_q.iter_mut().skip(filled_in) {
-            text.sections[0].value = String::new();
-        }
+    // The action bar only shows while the actions for a slot are open
+    let (actions, picked) = match (pointed_slot, menu.as_ref().and_then(|menu| menu.action)) {
+        (Some(slot), Some(action)) => (slot_actions(player_inv, slot, &item_db), Some(action)),
+        _ => (Vec::new(), None),
+    };
+    let pointed_held = pointed_slot == Some(active.0);
+    for (mut text, slot) in ui_actions_q.iter_mut() {
+        text.sections[0].value = match actions.get(slot.0) {
+            Some(action) => action_label(*action, pointed_held).to_string(),
+            None => String::new(),
+        };
+        text.sections[0].style.color = if picked == Some(slot.0) {
+            POINTER_COLOR
+        } else {
+            Color::BLACK
+        };
     }
+
+    for (mut text, header) in ui_info_q.iter_mut() {
+        text.sections[0].value = match (header, &menu) {
+            (Some(_), Some(menu)) => format!(
+                "filter: {:<14}sort: {:<14}page {}/{pages}",
+                filter_label(menu.filter),
+                menu.sort
+                    .map_or("none".to_string(), |key| format!("{key:?}").to_lowercase()),
+                page + 1
+            ),
+            (None, Some(menu)) if menu.info.is_empty() => "F filter  R sort  M merge  A/D page".to_string(),
+            (None, Some(menu)) => menu.info.clone(),
+            (_, None) => String::new(),
+        };
+    }
+}
+
+// Hides the inventory and goes back to the game world
+fn close_inventory(commands: &mut Commands, inventory_ui: &mut Visibility) {
+    inventory_ui.is_visible = false;
+    commands.remove_resource::<InventoryMenu>();
+    commands.insert_resource(NextState(GameState::Running));
 }
 
 // Toggles the game to inventory mode, game is shifted into Menu state so the game world pauses
@@ -275,6 +686,7 @@ fn ui_inventory_update(
 fn toggle_inventory(
     mut commands: Commands,
     mut inventory_ui_q: Query<&mut Visibility, With<InventoryUi>>,
+    state: Res<CurrentState<GameState>>,
     keeb: Res<Input<KeyCode>>,
     mut ev_invopen: EventWriter<InventoryUpdate>,
 ) {
@@ -283,33 +695,235 @@ fn toggle_inventory(
     }
 
     if let Ok(mut inventory_ui) = inventory_ui_q.get_single_mut() {
-        inventory_ui.is_visible = !inventory_ui.is_visible;
+        // Only opens from the game world, never on top of another menu
         if inventory_ui.is_visible {
+            close_inventory(&mut commands, &mut inventory_ui);
+        } else if state.0 == GameState::Running {
+            inventory_ui.is_visible = true;
+            commands.insert_resource(InventoryMenu::default());
             commands.insert_resource(NextState(GameState::Menu));
             ev_invopen.send(InventoryUpdate);
-        } else {
-            commands.insert_resource(NextState(GameState::Running));
         }
     }
 }
 
-// Moves what position in the inventory ui the player is currently pointing at
-// NOTE: need a way to get the item it is pointing at
-fn move_inventory_cursor(mut inv_pointer_q: Query<&mut InventoryPointer>, player_q: Query<&Inventory, With<Player>>, keeb: Res<Input<KeyCode>>) {
+// W/S moves the pointer, A/D flips through the pages and Enter opens the actions for the item pointed at
+// F shows one category at a time, R sorts by the next key and M merges stacks that were split up
+// While the actions are open A/D picks one, Enter does it and Backspace goes back to the slots
+fn move_inventory_cursor(
+    mut menu: ResMut<InventoryMenu>,
+    mut player_q: Query<&mut Inventory, With<Player>>,
+    item_db: Res<ItemDatabase>,
+    mut ev_action: EventWriter<InventoryActionEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    let mut player_inv = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("Did not find a player inventory"),
+    };
+
+    let view = visible_slots(&player_inv, menu.filter, &item_db);
+    // Slots drop out of a filtered view once they are emptied
+    menu.pointer = menu.pointer.min(view.len().saturating_sub(1));
+    let pointed_slot = view.get(menu.pointer).copied();
+
+    match menu.action {
+        None => {
+            if keeb.just_pressed(KeyCode::S) && menu.pointer + 1 < view.len() {
+                menu.pointer += 1;
+            } else if keeb.just_pressed(KeyCode::W) && menu.pointer > 0 {
+                menu.pointer -= 1;
+            } else if keeb.just_pressed(KeyCode::D) {
+                menu.pointer = (menu.pointer + INVENTORY_LINES).min(view.len().saturating_sub(1));
+            } else if keeb.just_pressed(KeyCode::A) {
+                menu.pointer = menu.pointer.saturating_sub(INVENTORY_LINES);
+            } else if keeb.just_pressed(KeyCode::F) {
+                let current = FILTERS.iter().position(|filter| *filter == menu.filter).unwrap_or(0);
+                menu.filter = FILTERS[(current + 1) % FILTERS.len()];
+                menu.pointer = 0;
+                menu.info = format!("showing {}", filter_label(menu.filter));
+            } else if keeb.just_pressed(KeyCode::R) {
+                let key = menu.sort.map_or(SortKey::Name, SortKey::next);
+                player_inv.sort(key, &item_db);
+                menu.sort = Some(key);
+                menu.info = format!("sorted by {key:?}").to_lowercase();
+            } else if keeb.just_pressed(KeyCode::M) {
+                player_inv.merge_stacks(&item_db);
+                menu.info = "merged stacks".to_string();
+            } else if keeb.just_pressed(KeyCode::Return)
+                && pointed_slot.is_some_and(|slot| player_inv.slot(slot).is_some())
+            {
+                menu.action = Some(0);
+            } else {
+                return;
+            }
+        }
+        Some(action) => {
+            let actions = match pointed_slot {
+                Some(slot) => slot_actions(&player_inv, slot, &item_db),
+                None => Vec::new(),
+            };
+            if keeb.just_pressed(KeyCode::D) && action + 1 < actions.len() {
+                menu.action = Some(action + 1);
+            } else if keeb.just_pressed(KeyCode::A) && action > 0 {
+                menu.action = Some(action - 1);
+            } else if keeb.just_pressed(KeyCode::Return) {
+                if let (Some(slot), Some(picked)) = (pointed_slot, actions.get(action)) {
+                    ev_action.send(InventoryActionEvent { slot, action: *picked });
+                }
+            } else if keeb.just_pressed(KeyCode::Back) {
+                menu.action = None;
+            } else {
+                return;
+            }
+        }
+    }
+    ev_invupdate.send(InventoryUpdate);
+}
+
+// Hovering a slot points at it and clicking it opens its actions, clicking an action does it
+fn select_inventory_slot(
+    mut menu: ResMut<InventoryMenu>,
+    lines_q: Query<(&Interaction, &InventoryLine), Changed<Interaction>>,
+    actions_q: Query<(&Interaction, &InventoryActionSlot), Changed<Interaction>>,
+    player_q: Query<&Inventory, With<Player>>,
+    item_db: Res<ItemDatabase>,
+    mut ev_action: EventWriter<InventoryActionEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+) {
     let player_inv = match player_q.get_single() {
         Ok(e) => e,
-        Err(_) => panic!("Did not find a player inventory")
+        Err(_) => panic!("Did not find a player inventory"),
+    };
+
+    let view = visible_slots(player_inv, menu.filter, &item_db);
+    let page_start = menu.pointer / INVENTORY_LINES * INVENTORY_LINES;
+    let mut changed = false;
+    for (interaction, line) in lines_q.iter() {
+        let position = page_start + line.0;
+        let slot = match view.get(position) {
+            Some(slot) => *slot,
+            None => continue,
+        };
+        match interaction {
+            // The pointer stays put while picking an action for it
+            Interaction::Hovered if menu.action.is_none() => menu.pointer = position,
+            Interaction::Clicked => {
+                menu.pointer = position;
+                menu.action = player_inv.slot(slot).map(|_| 0);
+            }
+            _ => continue,
+        }
+        changed = true;
+    }
+
+    if let (Some(slot), Some(_)) = (view.get(menu.pointer).copied(), menu.action) {
+        let actions = slot_actions(player_inv, slot, &item_db);
+        for (interaction, action) in actions_q.iter() {
+            let picked = match actions.get(action.0) {
+                Some(picked) => *picked,
+                None => continue,
+            };
+            match interaction {
+                Interaction::Hovered => menu.action = Some(action.0),
+                Interaction::Clicked => {
+                    menu.action = Some(action.0);
+                    ev_action.send(InventoryActionEvent { slot, action: picked });
+                }
+                Interaction::None => continue,
+            }
+            changed = true;
+        }
+    }
+
+    if changed {
+        ev_invupdate.send(InventoryUpdate);
+    }
+}
+
+/// Does what was picked from the actions on the slot it was picked for
+/// Dropped items land on the player's tile, equipping moves the item into the active hotbar slot
+/// and placeable items are used by building with them
+#[allow(clippy::too_many_arguments)]
+fn handle_inventory_action(
+    mut commands: Commands,
+    mut menu: ResMut<InventoryMenu>,
+    mut build_mode: ResMut<BuildMode>,
+    mut player_q: Query<(&EntityTilePos, &ActiveSlot, &mut Inventory), With<Player>>,
+    mut inventory_ui_q: Query<&mut Visibility, With<InventoryUi>>,
+    item_db: Res<ItemDatabase>,
+    mut ev_action: EventReader<InventoryActionEvent>,
+    mut ev_spawnitem: EventWriter<SpawnItemEvent>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+) {
+    let (pos, active, mut inventory) = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => panic!("found more than one player in inventory action fn"),
     };
 
-    if let Ok(mut inv_pointer) = inv_pointer_q.get_single_mut() {
-        if keeb.just_pressed(KeyCode::S) {
-            if inv_pointer.0 < player_inv.max_size {
-                inv_pointer.0 += 1;
+    for ev in ev_action.iter() {
+        let (id, amt) = match inventory.slot(ev.slot) {
+            Some(e) => e,
+            None => continue,
+        };
+        let item = item_db.items.get(&id);
+        let name = item.map_or("undefined", |item| item.name.as_str());
+
+        menu.info = match ev.action {
+            ItemAction::Use => match item {
+                Some(item) if item.consumable => {
+                    inventory.remove_from_slot(ev.slot, &ItemQuantity(1));
+                    format!("ate a {name}")
+                }
+                Some(item) if item.placeable.is_some() => {
+                    inventory.swap_slots(ev.slot, active.0);
+                    build_mode.0 = true;
+                    println!("building with {name}");
+                    if let Ok(mut inventory_ui) = inventory_ui_q.get_single_mut() {
+                        close_inventory(&mut commands, &mut inventory_ui);
+                    }
+                    return;
+                }
+                _ => format!("{name} can not be used"),
+            },
+            // Put away in the first empty slot past the hotbar
+            ItemAction::Equip if ev.slot == active.0 => {
+                match (HOTBAR_SLOTS..INVENTORY_SLOTS).find(|idx| inventory.slot(*idx).is_none()) {
+                    Some(empty) => {
+                        inventory.swap_slots(ev.slot, empty);
+                        format!("unequipped {name}")
+                    }
+                    None => "no empty slot to put it away in".to_string(),
+                }
             }
-        } else if keeb.just_pressed(KeyCode::W) {
-            if inv_pointer.0 > 0 {
-                inv_pointer.0 -= 1;
+            ItemAction::Equip => {
+                inventory.swap_slots(ev.slot, active.0);
+                format!("equipped {name}")
             }
+            ItemAction::Split => {
+                if inventory.split_slot(ev.slot) {
+                    format!("split the {name}")
+                } else {
+                    "no empty slot to split into".to_string()
+                }
+            }
+            ItemAction::Drop => {
+                inventory.take_slot(ev.slot);
+                ev_spawnitem.send(SpawnItemEvent::with_quantity(pos.x, pos.y, id, amt));
+                format!("dropped {} {name}", amt.0)
+            }
+            ItemAction::Inspect => match item {
+                Some(item) if !item.description.is_empty() => format!("{name}: {}", item.description),
+                _ => format!("{name}: nothing much to say about it"),
+            },
+        };
+        println!("{}", menu.info);
+
+        // The slot may have changed under the actions, inspecting leaves them open
+        if ev.action != ItemAction::Inspect {
+            menu.action = None;
         }
+        ev_invupdate.send(InventoryUpdate);
     }
 }
@@ -1,12 +1,21 @@
 /// Item Utilities
 ///
 /// Includes systems to spawn items
-/// Contains the database for items
+/// Contains the database for items and the loot tables of world objs
 use bevy::{prelude::*, utils::HashMap};
 use bevy_ecs_tilemap::prelude::*;
 use iyes_loopless::prelude::*;
+use rand::{
+    distributions::{Distribution, WeightedIndex},
+    Rng,
+};
 
-use crate::{world_gen::ItemStorage, GameState};
+use crate::{
+    crafting::Station,
+    interact::ObjectKind,
+    world_gen::{tiles_around, world_tile, Layer, WorldChanges, WorldTilesMut},
+    GameState,
+};
 use serde::Deserialize;
 use std::{error::Error, fs};
 
@@ -16,26 +25,87 @@ impl Plugin for ItemUtilPlugin {
     fn build(&self, app: &mut App) {
         app.add_event::<SpawnItemEvent>()
             .add_enter_system(GameState::GameLoading, init_item_database)
+            .add_enter_system(GameState::GameLoading, init_loot_tables)
+            // Items are dropped from the inventory too, while the game is paused
             .add_system(
                 spawn_item_at_xy
-                    .run_in_state(GameState::Running)
+                    .run_not_in_state(GameState::AssetLoading)
                     .run_on_event::<SpawnItemEvent>(),
             );
     }
 }
 
-// Maps all items to a unique u32, loaded on startup and should not be mutated at runtime
+pub const ITEMS_FILE: &str = "assets/items/comfort_items.json";
+const DEFAULT_MAX_STACK: u32 = 99;
+
+// Maps all items to a unique u32, loaded on startup and replaced whenever the json changes
 #[derive(Resource)]
 pub struct ItemDatabase {
     pub items: HashMap<ItemId, Item>,
 }
 
+impl ItemDatabase {
+    /// How many of the item fit in a single inventory slot
+    pub fn max_stack(&self, id: ItemId) -> u32 {
+        self.items.get(&id).map_or(DEFAULT_MAX_STACK, |item| item.max_stack)
+    }
+}
+
 // Static information about the item that is the same across all of its kind
 #[derive(Deserialize, Debug, Component, Clone)]
 pub struct Item {
     pub id: ItemId,       // unique identifier for the item
     pub name: String,     // name of item
     pub atlas_index: u32, // sprite index for the atlas
+    #[serde(default)]
+    pub description: String, // shown when inspecting the item in the inventory
+    #[serde(default)]
+    pub consumable: bool, // can be eaten straight from the inventory
+    #[serde(default)]
+    pub category: ItemCategory, // what the inventory can be filtered and sorted by
+    #[serde(default = "default_max_stack")]
+    pub max_stack: u32, // most that fit in a single inventory slot
+    #[serde(default)]
+    pub tool: Option<Tool>, // set when the item can be equipped to harvest with
+    #[serde(default)]
+    pub placeable: Option<Placeable>, // set when the item can be built into the world
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ItemCategory {
+    #[default]
+    Resource,
+    Tool,
+    Placeable,
+    Food,
+}
+
+// What an equipped item does when harvesting with it
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Tool {
+    pub kind: ToolKind,
+    pub damage: i32,
+}
+
+// The world obj an item turns into when it is built
+#[derive(Deserialize, Debug, Clone, Copy)]
+pub struct Placeable {
+    pub sprite: u32, // sprite index for the world_objs atlas
+    #[serde(default)]
+    pub blocking: bool,
+    #[serde(default)]
+    pub station: Option<Station>, // set when recipes can be crafted next to it
+    #[serde(default)]
+    pub container: bool, // built as a chest with its own inventory
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ToolKind {
+    Pickaxe,
+    Hatchet,
+    Shovel,
 }
 
 // Note: ItemId and ItemQuantity are often used together why not join them
@@ -47,63 +117,251 @@ pub struct ItemQuantity(pub u32);
 #[derive(Deserialize, Debug, Copy, Clone, Hash, Eq, PartialEq)]
 pub struct ItemId(pub u32);
 
+fn default_max_stack() -> u32 {
+    DEFAULT_MAX_STACK
+}
+
 fn init_item_database(mut commands: Commands) {
-    let items = match load_items_from_json() {
-        Ok(items) => items,
+    match load_item_database() {
+        Ok(item_db) => commands.insert_resource(item_db),
         Err(err) => panic!("Could not load items from json, {}", err),
     };
+}
 
+pub fn load_item_database() -> Result<ItemDatabase, Box<dyn Error>> {
     let mut item_db = HashMap::new();
-    for item in items {
+    for item in load_items_from_json()? {
+        if item.max_stack == 0 {
+            return Err(format!("{} needs a max_stack of at least 1", item.name).into());
+        }
         item_db.insert(item.id, item);
     }
-
-    commands.insert_resource(ItemDatabase { items: item_db });
+    Ok(ItemDatabase { items: item_db })
 }
 
 /// Attempts to load item definitions from a json file
-fn load_items_from_json() -> Result<Vec<Item>, Box<dyn Error>> {
-    let contents = fs::read_to_string("assets/items/comfort_items.json")?;
+pub fn load_items_from_json() -> Result<Vec<Item>, Box<dyn Error>> {
+    let contents = fs::read_to_string(ITEMS_FILE)?;
     let items: Vec<Item> = serde_json::from_str(&contents)?;
     Ok(items)
 }
 
+pub const LOOT_FILE: &str = "assets/items/comfort_loot.json";
+
+// What every kind of world obj drops when destroyed, loaded on startup and replaced whenever the json changes
+#[derive(Resource)]
+pub struct LootTables {
+    pub tables: HashMap<ObjectKind, LootTable>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LootTable {
+    object: ObjectKind,
+    // How many times a drop gets picked from the table
+    #[serde(default = "one")]
+    rolls: u32,
+    drops: Vec<LootDrop>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct LootDrop {
+    item_id: ItemId,
+    // How likely this drop gets picked compared to the others in the table
+    #[serde(default = "one")]
+    weight: u32,
+    #[serde(default = "one")]
+    min: u32,
+    #[serde(default = "one")]
+    max: u32,
+    // Once picked the odds of it actually dropping, between 0 and 1
+    #[serde(default = "always")]
+    chance: f64,
+}
+
+fn one() -> u32 {
+    1
+}
+
+fn always() -> f64 {
+    1.0
+}
+
+impl LootTable {
+    /// Picks what a destroyed obj leaves behind, picks of the same item are added together
+    pub fn roll(&self, rng: &mut impl Rng) -> Vec<(ItemId, ItemQuantity)> {
+        let mut loot: Vec<(ItemId, ItemQuantity)> = Vec::new();
+        let picker = match WeightedIndex::new(self.drops.iter().map(|drop| drop.weight)) {
+            Ok(picker) => picker,
+            Err(_) => return loot,
+        };
+
+        for _ in 0..self.rolls {
+            let drop = &self.drops[picker.sample(rng)];
+            if !rng.gen_bool(drop.chance) {
+                continue;
+            }
+            let amt = rng.gen_range(drop.min..=drop.max);
+            match loot.iter_mut().find(|(id, _)| *id == drop.item_id) {
+                Some((_, qty)) => qty.0 += amt,
+                None => loot.push((drop.item_id, ItemQuantity(amt))),
+            }
+        }
+        loot.retain(|(_, qty)| qty.0 > 0);
+        loot
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.drops.iter().all(|drop| drop.weight == 0) {
+            return Err(format!(
+                "{:?} needs at least one drop with a weight above 0",
+                self.object
+            ));
+        }
+        for drop in self.drops.iter() {
+            if drop.min > drop.max {
+                return Err(format!(
+                    "{:?} drops {:?} with a min above its max",
+                    self.object, drop.item_id
+                ));
+            }
+            if !(0.0..=1.0).contains(&drop.chance) {
+                return Err(format!(
+                    "{:?} drops {:?} with a chance outside of 0 to 1",
+                    self.object, drop.item_id
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn init_loot_tables(mut commands: Commands) {
+    match load_loot_tables() {
+        Ok(loot) => commands.insert_resource(loot),
+        Err(err) => panic!("Could not load loot tables from json, {}", err),
+    };
+}
+
+pub fn load_loot_tables() -> Result<LootTables, Box<dyn Error>> {
+    let contents = fs::read_to_string(LOOT_FILE)?;
+    let mut tables = HashMap::new();
+    for table in serde_json::from_str::<Vec<LootTable>>(&contents)? {
+        table.validate()?;
+        let object = table.object;
+        if tables.insert(object, table).is_some() {
+            return Err(format!("{object:?} has more than one loot table").into());
+        }
+    }
+    Ok(LootTables { tables })
+}
+
+const DROP_SEARCH_RADIUS: i32 = 3; // how far a dropped item looks for a free tile before it's lost
+
+/// Drops items onto the ground, they stack with the same item already lying on the tile
+/// A tile holding a different item is left alone and the drop spills onto the nearest free tile instead
 pub struct SpawnItemEvent {
     x: u32,
     y: u32,
     item_id: ItemId,
+    quantity: ItemQuantity,
 }
 
 impl SpawnItemEvent {
     pub fn from(x: u32, y: u32, item_id: ItemId) -> SpawnItemEvent {
-        SpawnItemEvent { x, y, item_id }
+        SpawnItemEvent::with_quantity(x, y, item_id, ItemQuantity(1))
+    }
+
+    pub fn with_quantity(x: u32, y: u32, item_id: ItemId, quantity: ItemQuantity) -> SpawnItemEvent {
+        SpawnItemEvent {
+            x,
+            y,
+            item_id,
+            quantity,
+        }
     }
 }
 
 fn spawn_item_at_xy(
     mut commands: Commands,
-    mut tile_storage_q: Query<(Entity, &mut TileStorage), With<ItemStorage>>,
+    mut world_tiles: WorldTilesMut,
+    mut changes: ResMut<WorldChanges>,
+    mut ground_items_q: Query<(&Item, &mut ItemQuantity)>,
     item_db: Res<ItemDatabase>,
     mut ev_spawnitem: EventReader<SpawnItemEvent>,
 ) {
+    // Works out where every drop lands first, drops of the same item on the same tile in one frame
+    // end up as a single stack
+    let mut stacks: Vec<(TilePos, ItemId, u32)> = Vec::new();
     for ev in ev_spawnitem.iter() {
-        if let Ok((tiles_entity, mut item_tiles)) = tile_storage_q.get_single_mut() {
-            let tile_pos = TilePos { x: ev.x, y: ev.y };
-            if let Some(item) = item_db.items.get(&ev.item_id) {
-                let item_entity = commands
-                    .spawn((
-                        TileBundle {
-                            position: tile_pos,
-                            texture_index: TileTextureIndex(item.atlas_index),
-                            tilemap_id: TilemapId(tiles_entity),
-                            ..default()
-                        },
-                        item.clone(),
-                        ItemQuantity(1),
-                    ))
-                    .id();
-                item_tiles.set(&tile_pos, item_entity);
+        let landing = tiles_around(TilePos { x: ev.x, y: ev.y }, DROP_SEARCH_RADIUS).find(|tile_pos| {
+            if let Some((_, id, _)) = stacks.iter().find(|(pos, _, _)| pos == tile_pos) {
+                return *id == ev.item_id;
+            }
+            // Chunks that aren't generated keep their items in the world changes
+            if world_tiles.tilemap(Layer::Items, tile_pos).is_none() {
+                return match changes.ground_items.get(tile_pos) {
+                    Some((id, _)) => *id == ev.item_id,
+                    None => true,
+                };
+            }
+            // Entities that are not ground items anymore are leftovers and get replaced
+            match world_tiles
+                .get(Layer::Items, tile_pos)
+                .and_then(|entity| ground_items_q.get(entity).ok())
+            {
+                Some((item, _)) => item.id == ev.item_id,
+                None => true,
+            }
+        });
+        let tile_pos = match landing {
+            Some(tile_pos) => tile_pos,
+            None => {
+                println!("no free tile near {} {} to drop the item on", ev.x, ev.y);
+                continue;
             }
+        };
+
+        match stacks
+            .iter_mut()
+            .find(|(pos, id, _)| *pos == tile_pos && *id == ev.item_id)
+        {
+            Some((_, _, amt)) => *amt += ev.quantity.0,
+            None => stacks.push((tile_pos, ev.item_id, ev.quantity.0)),
+        }
+    }
+
+    for (tile_pos, item_id, amt) in stacks {
+        let tilemap = match world_tiles.tilemap(Layer::Items, &tile_pos) {
+            Some(tilemap) => tilemap,
+            None => {
+                let (_, qty) = changes
+                    .ground_items
+                    .entry(tile_pos)
+                    .or_insert((item_id, ItemQuantity(0)));
+                qty.0 += amt;
+                continue;
+            }
+        };
+        // Lands on top of a stack of the same item that is already lying there
+        if let Some(existing) = world_tiles.get(Layer::Items, &tile_pos) {
+            if let Ok((item, mut qty)) = ground_items_q.get_mut(existing) {
+                if item.id == item_id {
+                    qty.0 += amt;
+                    continue;
+                }
+            }
+        }
+
+        if let Some(item) = item_db.items.get(&item_id) {
+            let item_entity = commands
+                .spawn((
+                    world_tile(tilemap, &tile_pos, item.atlas_index),
+                    item.clone(),
+                    ItemQuantity(amt),
+                ))
+                .id();
+            world_tiles.set(Layer::Items, &tile_pos, item_entity);
+            commands.entity(tilemap).add_child(item_entity);
         }
     }
 }
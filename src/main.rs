@@ -17,8 +17,23 @@ mod item_util;
 use item_util::ItemUtilPlugin;
 mod inventory;
 use inventory::InventoryPlugin;
+mod hotbar;
+use hotbar::HotbarPlugin;
 mod crafting;
 use crafting::CraftingPlugin;
+mod save_game;
+use save_game::SaveGamePlugin;
+mod worldgen_cli;
+mod building;
+use building::BuildingPlugin;
+mod chest;
+use chest::ChestPlugin;
+mod hot_reload;
+use hot_reload::HotReloadPlugin;
+mod spawn_point;
+use spawn_point::SpawnPointPlugin;
+mod pathfinding;
+use pathfinding::PathfindingPlugin;
 
 use bevy::prelude::*;
 use bevy::window::PresentMode;
@@ -34,6 +49,12 @@ pub enum GameState {
 }
 
 fn main() {
+    // `--generate <name>` only writes out a world preview, the game never starts
+    if let Some(options) = worldgen_cli::options_from_args() {
+        worldgen_cli::run(options);
+        return;
+    }
+
     App::new()
         .add_loopless_state(GameState::AssetLoading) // Starting state which leads to the plugin doing its job first
         .add_plugin(DefaultPluginsWithImage)
@@ -42,10 +63,17 @@ fn main() {
         .add_plugin(WorldGenerationPlugin)
         .add_plugin(CameraPlugin)
         .add_plugin(PlayerPlugin)
+        .add_plugin(SpawnPointPlugin)
+        .add_plugin(PathfindingPlugin)
         .add_plugin(InteractPlugin)
         .add_plugin(ItemUtilPlugin)
         .add_plugin(InventoryPlugin)
+        .add_plugin(HotbarPlugin)
         .add_plugin(CraftingPlugin)
+        .add_plugin(BuildingPlugin)
+        .add_plugin(ChestPlugin)
+        .add_plugin(SaveGamePlugin) // after WorldGenerationPlugin so a loaded save can override the seed
+        .add_plugin(HotReloadPlugin)
         .add_system(run_game.run_in_state(GameState::GameLoading))
         .add_system(bevy::window::close_on_esc)
         .run();
@@ -0,0 +1,378 @@
+/// Pathfinding
+///
+/// A* over the tile grid, paths only take straight steps just like the player does
+/// Anything with a position and a direction can be sent somewhere with a PathRequest,
+/// the path found is then walked one MoveEvent at a time. Clicking on the world sends the player there
+use bevy::{prelude::*, utils::HashMap};
+use bevy_ecs_tilemap::prelude::*;
+use iyes_loopless::prelude::*;
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, VecDeque},
+    time::Duration,
+};
+
+use crate::{
+    entity_tile_pos::EntityTilePos,
+    player::{Direction, MoveEvent, Player, SystemOrder, DIRECTION_KEYS, PLAYER_MOVE_TIMER_MS},
+    world_gen::{straight_neighbours, world_to_tile, WorldTiles},
+    GameState,
+};
+
+const MAX_SEARCH_TILES: usize = 4096; // Tiles looked at before a target counts as unreachable
+
+pub struct PathfindingPlugin;
+
+impl Plugin for PathfindingPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PathRequest>().add_system_set(
+            ConditionSet::new()
+                .run_in_state(GameState::Running)
+                .label(SystemOrder::Input)
+                .before(SystemOrder::Logic)
+                .with_system(click_to_move)
+                .with_system(plan_paths)
+                .with_system(follow_path)
+                .into(),
+        );
+    }
+}
+
+/// Asks for the entity to walk over to the goal
+pub struct PathRequest {
+    pub entity: Entity,
+    pub goal: TilePos,
+}
+
+/// The steps left on the way to the goal, removed once the goal is reached or can't be reached anymore
+#[derive(Component)]
+pub struct FollowPath {
+    goal: TilePos,
+    steps: VecDeque<TilePos>,
+    timer: Timer,
+}
+
+impl FollowPath {
+    fn new(goal: TilePos, steps: Vec<TilePos>) -> FollowPath {
+        let step_time = Duration::from_millis(PLAYER_MOVE_TIMER_MS);
+        let mut timer = Timer::new(step_time, TimerMode::Repeating);
+        // The first step is taken straight away
+        timer.set_elapsed(step_time);
+        FollowPath {
+            goal,
+            steps: steps.into(),
+            timer,
+        }
+    }
+}
+
+/// The steps from start to goal, not including the start, None when the goal can't be reached
+/// Gives up after looking at MAX_SEARCH_TILES tiles, so a goal walled off in a huge open area fails quickly
+pub fn find_path(start: TilePos, goal: TilePos, walkable: impl Fn(&TilePos) -> bool) -> Option<Vec<TilePos>> {
+    if start == goal {
+        return Some(Vec::new());
+    }
+    if !walkable(&goal) {
+        return None;
+    }
+
+    // Cheapest estimate first, ties go to whichever is closer to the goal
+    let mut open = BinaryHeap::new();
+    let mut costs: HashMap<TilePos, u32> = HashMap::new();
+    let mut came_from: HashMap<TilePos, TilePos> = HashMap::new();
+    let to_goal = distance(start, goal);
+    open.push(Reverse((to_goal, to_goal, start.x, start.y)));
+    costs.insert(start, 0);
+
+    while let Some(Reverse((estimate, to_goal, x, y))) = open.pop() {
+        let current = TilePos { x, y };
+        if current == goal {
+            return Some(walk_back(&came_from, start, goal));
+        }
+        // A cheaper way here was found after this one was queued
+        let cost = estimate - to_goal;
+        if costs.get(&current).is_some_and(|known| *known < cost) {
+            continue;
+        }
+        if costs.len() > MAX_SEARCH_TILES {
+            return None;
+        }
+
+        for next in straight_neighbours(current) {
+            let next_cost = cost + 1;
+            if costs.get(&next).is_some_and(|known| *known <= next_cost) || !walkable(&next) {
+                continue;
+            }
+            costs.insert(next, next_cost);
+            came_from.insert(next, current);
+            let to_goal = distance(next, goal);
+            open.push(Reverse((next_cost + to_goal, to_goal, next.x, next.y)));
+        }
+    }
+
+    None
+}
+
+// Steps needed between two tiles when nothing is in the way
+fn distance(a: TilePos, b: TilePos) -> u32 {
+    a.x.abs_diff(b.x) + a.y.abs_diff(b.y)
+}
+
+fn walk_back(came_from: &HashMap<TilePos, TilePos>, start: TilePos, goal: TilePos) -> Vec<TilePos> {
+    let mut path = vec![goal];
+    let mut current = goal;
+    while let Some(previous) = came_from.get(&current) {
+        if *previous == start {
+            break;
+        }
+        path.push(*previous);
+        current = *previous;
+    }
+    path.reverse();
+    path
+}
+
+// The direction a single straight step faces
+fn step_direction(from: &EntityTilePos, to: &TilePos) -> Direction {
+    if to.y > from.y {
+        Direction::Up
+    } else if to.y < from.y {
+        Direction::Down
+    } else if to.x < from.x {
+        Direction::Left
+    } else {
+        Direction::Right
+    }
+}
+
+// Left click on a tile walks the player over to it
+fn click_to_move(
+    player_q: Query<Entity, With<Player>>,
+    camera_q: Query<(&Camera, &GlobalTransform), With<Camera2d>>,
+    windows: Res<Windows>,
+    mouse: Res<Input<MouseButton>>,
+    mut ev_path: EventWriter<PathRequest>,
+) {
+    if !mouse.just_pressed(MouseButton::Left) {
+        return;
+    }
+
+    let cursor = match windows.get_primary().and_then(|window| window.cursor_position()) {
+        Some(cursor) => cursor,
+        None => return,
+    };
+    let (camera, camera_transform) = match camera_q.get_single() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    let world_pos = match camera.viewport_to_world(camera_transform, cursor) {
+        Some(ray) => ray.origin.truncate(),
+        None => return,
+    };
+    let goal = match world_to_tile(world_pos) {
+        Some(tile_pos) => tile_pos,
+        None => return,
+    };
+
+    if let Ok(player) = player_q.get_single() {
+        ev_path.send(PathRequest { entity: player, goal });
+    }
+}
+
+fn plan_paths(
+    mut commands: Commands,
+    movers_q: Query<&EntityTilePos>,
+    world_tiles: WorldTiles,
+    mut ev_path: EventReader<PathRequest>,
+) {
+    for ev in ev_path.iter() {
+        let pos = match movers_q.get(ev.entity) {
+            Ok(pos) => pos,
+            Err(_) => continue,
+        };
+
+        let start = TilePos { x: pos.x, y: pos.y };
+        match find_path(start, ev.goal, |tile_pos| world_tiles.walkable(tile_pos)) {
+            Some(steps) => {
+                commands.entity(ev.entity).insert(FollowPath::new(ev.goal, steps));
+            }
+            None => {
+                println!("no way to get to {} {}", ev.goal.x, ev.goal.y);
+                commands.entity(ev.entity).remove::<FollowPath>();
+            }
+        }
+    }
+}
+
+// Takes the next step every PLAYER_MOVE_TIMER_MS, finding a new way when something got in the way since
+fn follow_path(
+    mut commands: Commands,
+    mut movers_q: Query<(Entity, &EntityTilePos, &mut Direction, &mut FollowPath, Option<&Player>)>,
+    world_tiles: WorldTiles,
+    mut ev_moveplayer: EventWriter<MoveEvent>,
+    keeb: Res<Input<KeyCode>>,
+    time: Res<Time>,
+) {
+    for (entity, pos, mut direction, mut path, player) in movers_q.iter_mut() {
+        // Walking by hand takes over from the path
+        if player.is_some() && keeb.any_pressed(DIRECTION_KEYS.map(|(key, _)| key)) {
+            commands.entity(entity).remove::<FollowPath>();
+            continue;
+        }
+
+        path.timer.tick(time.delta());
+        if !path.timer.just_finished() {
+            continue;
+        }
+
+        let current = TilePos { x: pos.x, y: pos.y };
+        if path.steps.front().is_some_and(|next| !world_tiles.walkable(next)) {
+            match find_path(current, path.goal, |tile_pos| world_tiles.walkable(tile_pos)) {
+                Some(steps) => path.steps = steps.into(),
+                None => {
+                    println!("the way to {} {} is blocked", path.goal.x, path.goal.y);
+                    commands.entity(entity).remove::<FollowPath>();
+                    continue;
+                }
+            }
+        }
+
+        let next = match path.steps.pop_front() {
+            Some(next) => next,
+            None => {
+                commands.entity(entity).remove::<FollowPath>();
+                continue;
+            }
+        };
+        *direction = step_direction(pos, &next);
+        ev_moveplayer.send(MoveEvent(entity, next));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Rows are listed top to bottom, '#' is in the way and anything else can be walked on
+    // The letters mark tiles to look up with `tile`
+    fn grid<'a>(rows: &'a [&'a str]) -> impl Fn(&TilePos) -> bool + 'a {
+        move |tile_pos: &TilePos| match cell(rows, tile_pos) {
+            Some(c) => c != '#',
+            None => false,
+        }
+    }
+
+    fn cell(rows: &[&str], tile_pos: &TilePos) -> Option<char> {
+        let row = rows.len().checked_sub(tile_pos.y as usize + 1)?;
+        rows[row].chars().nth(tile_pos.x as usize)
+    }
+
+    fn tile(rows: &[&str], mark: char) -> TilePos {
+        for (row, line) in rows.iter().enumerate() {
+            if let Some(x) = line.chars().position(|c| c == mark) {
+                let y = rows.len() - 1 - row;
+                return TilePos {
+                    x: x as u32,
+                    y: y as u32,
+                };
+            }
+        }
+        panic!("no {mark} in the grid");
+    }
+
+    // Every step is a single straight step onto a walkable tile and the last one is the goal
+    fn assert_walkable_path(rows: &[&str], start: TilePos, goal: TilePos, path: &[TilePos]) {
+        let walkable = grid(rows);
+        let mut previous = start;
+        for step in path {
+            assert_eq!(
+                distance(previous, *step),
+                1,
+                "{previous:?} to {step:?} is not a single step"
+            );
+            assert!(walkable(step), "{step:?} is not walkable");
+            previous = *step;
+        }
+        assert_eq!(previous, goal);
+    }
+
+    #[test]
+    fn straight_line_on_open_ground() {
+        let rows = ["S....G"];
+        let (start, goal) = (tile(&rows, 'S'), tile(&rows, 'G'));
+
+        let path = find_path(start, goal, grid(&rows)).unwrap();
+        assert_eq!(path.len(), 5);
+        assert_walkable_path(&rows, start, goal, &path);
+    }
+
+    #[test]
+    fn goes_around_a_wall() {
+        let rows = [
+            ".....", //
+            ".###.", //
+            "S.#.G", //
+            "..#..", //
+        ];
+        let (start, goal) = (tile(&rows, 'S'), tile(&rows, 'G'));
+
+        let path = find_path(start, goal, grid(&rows)).unwrap();
+        // Up two, across four and down two
+        assert_eq!(path.len(), 8);
+        assert_walkable_path(&rows, start, goal, &path);
+    }
+
+    #[test]
+    fn takes_the_shortest_of_two_ways() {
+        let rows = [
+            "...........", //
+            ".#########.", //
+            "S....#....G", //
+            ".###...###.", //
+        ];
+        let (start, goal) = (tile(&rows, 'S'), tile(&rows, 'G'));
+
+        let path = find_path(start, goal, grid(&rows)).unwrap();
+        // Dipping under the middle wall is two steps longer than a straight line, the top is four
+        assert_eq!(path.len(), 12);
+        assert_walkable_path(&rows, start, goal, &path);
+    }
+
+    #[test]
+    fn start_is_the_goal() {
+        let rows = ["S"];
+        let start = tile(&rows, 'S');
+
+        assert_eq!(find_path(start, start, grid(&rows)), Some(Vec::new()));
+    }
+
+    #[test]
+    fn goal_in_the_way_is_unreachable() {
+        let rows = ["S..#"];
+        let goal = TilePos { x: 3, y: 0 };
+
+        assert_eq!(find_path(tile(&rows, 'S'), goal, grid(&rows)), None);
+    }
+
+    #[test]
+    fn walled_off_goal_is_unreachable() {
+        let rows = [
+            "S....", //
+            "..###", //
+            "..#G#", //
+            "..###", //
+        ];
+
+        assert_eq!(find_path(tile(&rows, 'S'), tile(&rows, 'G'), grid(&rows)), None);
+    }
+
+    #[test]
+    fn gives_up_on_walled_off_goal_in_open_world() {
+        let start = TilePos { x: 100, y: 100 };
+        let goal = TilePos { x: 140, y: 100 };
+        // Everything is open except for a ring around the goal
+        let walkable = |tile_pos: &TilePos| distance(*tile_pos, goal) != 1;
+
+        assert_eq!(find_path(start, goal, walkable), None);
+    }
+}
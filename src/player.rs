@@ -3,20 +3,27 @@ use std::time::Duration;
 use bevy::prelude::*;
 use bevy_ecs_tilemap::prelude::*;
 use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     assets::SpriteAssets,
+    building::BuildMode,
+    crafting::CraftingQueue,
     effects::lerp,
     entity_tile_pos::EntityTilePos,
+    hotbar::ActiveSlot,
     interact::{HarvestInteraction, Interact},
     inventory::Inventory,
-    world_gen::{within_bounds, Blocking, ObjectSize},
+    spawn_point::{preferred_spawn, NeedsSpawnPoint},
+    world_gen::{offset_tile, Blocking, ObjectSize, WorldTiles},
     GameState,
 };
 
 pub const PLAYER_Z: f32 = 50.0;
-const PLAYER_TILE_SPEED: u32 = 1;
-const PLAYER_MOVE_TIMER_MS: u64 = 175;
+const PLAYER_TILE_SPEED: i32 = 1;
+pub const PLAYER_MOVE_TIMER_MS: u64 = 175;
+// A direction let go of sooner than this was a tap and only turns the player
+const PLAYER_HOLD_DELAY_MS: u64 = 120;
 
 pub struct PlayerPlugin;
 
@@ -35,7 +42,6 @@ impl Plugin for PlayerPlugin {
             .add_system(
                 move_player
                     .run_in_state(GameState::Running)
-                    // .run_if(movement_cooldown)
                     .label(SystemOrder::Logic)
                     .after(SystemOrder::Input),
             )
@@ -67,9 +73,9 @@ impl Plugin for PlayerPlugin {
 pub struct Player;
 
 #[derive(Component)]
-struct PlayerTarget;
+pub struct PlayerTarget;
 
-#[derive(Component)]
+#[derive(Component, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Direction {
     Up,
     Down,
@@ -77,24 +83,48 @@ pub enum Direction {
     Right,
 }
 
+/// Paces the steps while a direction is held
 #[derive(Component)]
-struct HeldTimer(Timer);
+struct HeldTimer {
+    // Runs from the press until the key counts as held
+    hold: Timer,
+    // Paces the steps after the first one
+    repeat: Timer,
+}
 
-fn setup_character(mut commands: Commands, sprites: Res<SpriteAssets>, _blocking_q: Query<&TilePos, With<Blocking>>) {
-    // TODO: Find first nonblocking tilepos
-    let starting_pos = EntityTilePos { x: 64, y: 64 };
+impl HeldTimer {
+    fn new() -> HeldTimer {
+        HeldTimer {
+            hold: Timer::new(Duration::from_millis(PLAYER_HOLD_DELAY_MS), TimerMode::Once),
+            repeat: Timer::new(Duration::from_millis(PLAYER_MOVE_TIMER_MS), TimerMode::Repeating),
+        }
+    }
+
+    fn reset(&mut self) {
+        self.hold.reset();
+        self.repeat.reset();
+    }
+}
+
+fn setup_character(mut commands: Commands, sprites: Res<SpriteAssets>) {
+    // Moved onto a free tile once the world around it is generated
+    let starting_pos = preferred_spawn();
+    let world_pos = starting_pos.center_in_world();
 
     commands.spawn((
         SpriteSheetBundle {
             texture_atlas: sprites.characters.clone(),
-            transform: Transform::from_xyz(starting_pos.x as f32 * 8.0, starting_pos.y as f32 * 8.0, 50.0),
+            transform: Transform::from_xyz(world_pos.x, world_pos.y, 50.0),
             ..default()
         },
         Player,
         Direction::Down,
         starting_pos,
-        HeldTimer(Timer::new(Duration::from_millis(PLAYER_MOVE_TIMER_MS), TimerMode::Repeating)),
+        HeldTimer::new(),
         Inventory::new(),
+        ActiveSlot::default(),
+        CraftingQueue::default(),
+        NeedsSpawnPoint,
     ));
 
     println!("Created player succesfully");
@@ -102,7 +132,7 @@ fn setup_character(mut commands: Commands, sprites: Res<SpriteAssets>, _blocking
     commands.spawn((
         SpriteBundle {
             texture: sprites.target.clone(),
-            transform: Transform::from_xyz(starting_pos.x as f32 * 8.0, starting_pos.y as f32 * 8.0 - 8.0, 50.0),
+            transform: Transform::from_xyz(world_pos.x, world_pos.y - 8.0, 50.0),
             ..default()
         },
         PlayerTarget,
@@ -114,23 +144,18 @@ fn setup_character(mut commands: Commands, sprites: Res<SpriteAssets>, _blocking
 fn move_target(player_q: Query<(&EntityTilePos, &Direction)>, mut target_q: Query<&mut Transform, With<PlayerTarget>>) {
     if let Ok((player_tile_pos, dir)) = player_q.get_single() {
         if let Ok(mut target) = target_q.get_single_mut() {
+            let world_pos = player_tile_pos.center_in_world();
             target.translation = match *dir {
-                Direction::Up => Vec3::new(player_tile_pos.x as f32 * 8.0, player_tile_pos.y as f32 * 8.0 + 8.0, 50.0),
-                Direction::Down => {
-                    Vec3::new(player_tile_pos.x as f32 * 8.0, player_tile_pos.y as f32 * 8.0 - 8.0, 50.0)
-                }
-                Direction::Left => {
-                    Vec3::new(player_tile_pos.x as f32 * 8.0 - 8.0, player_tile_pos.y as f32 * 8.0, 50.0)
-                }
-                Direction::Right => {
-                    Vec3::new(player_tile_pos.x as f32 * 8.0 + 8.0, player_tile_pos.y as f32 * 8.0, 50.0)
-                }
+                Direction::Up => Vec3::new(world_pos.x, world_pos.y + 8.0, 50.0),
+                Direction::Down => Vec3::new(world_pos.x, world_pos.y - 8.0, 50.0),
+                Direction::Left => Vec3::new(world_pos.x - 8.0, world_pos.y, 50.0),
+                Direction::Right => Vec3::new(world_pos.x + 8.0, world_pos.y, 50.0),
             };
         }
     };
 }
 
-struct MoveEvent(Entity, TilePos);
+pub struct MoveEvent(pub Entity, pub TilePos);
 
 /// Moves player entity from input
 fn move_player(mut player_q: Query<&mut EntityTilePos>, mut ev_move: EventReader<MoveEvent>) {
@@ -142,13 +167,6 @@ fn move_player(mut player_q: Query<&mut EntityTilePos>, mut ev_move: EventReader
     }
 }
 
-// Returns true if the timer has finished in the frame
-fn movement_cooldown(mut timer_q: Query<&mut HeldTimer, With<Player>>, time: Res<Time>) -> bool {
-    let mut move_time = timer_q.single_mut();
-    move_time.0.tick(time.delta());
-    move_time.0.finished()
-}
-
 /// Updates the sprite position based on a discrete position in the entity
 fn update_sprite_position<Type: Component>(mut entity_q: Query<(&mut Transform, &EntityTilePos), With<Type>>) {
     let (mut sprite_pos, entity_actual_pos) = entity_q.single_mut();
@@ -158,63 +176,92 @@ fn update_sprite_position<Type: Component>(mut entity_q: Query<(&mut Transform,
     sprite_pos.translation = Vec3::new(lerped_pos.x, lerped_pos.y, PLAYER_Z);
 }
 
-/// Checks to ensure dest tile is inbounds of the map
+// Checked in this order, so when two keys go down in the same frame the first one wins
+pub const DIRECTION_KEYS: [(KeyCode, Direction); 4] = [
+    (KeyCode::W, Direction::Up),
+    (KeyCode::S, Direction::Down),
+    (KeyCode::A, Direction::Left),
+    (KeyCode::D, Direction::Right),
+];
+
+/// Pressing a direction turns the player to face it, a tap does nothing more. Once the key is held for
+/// PLAYER_HOLD_DELAY_MS the player steps straight away and then keeps stepping every PLAYER_MOVE_TIMER_MS
+///
+/// Only one direction moves the player at a time so every step is a single tile along one axis and goes
+/// through the blocking checks. A key going down takes over from the ones already held, keys going down
+/// in the same frame are picked in the order of DIRECTION_KEYS
 fn directional_input_handle(
-    mut player_q: Query<(Entity, &EntityTilePos, &mut Direction), With<Player>>,
-    obj_tiles_q: Query<(&TilePos, Option<&ObjectSize>, Option<&Blocking>)>,
+    mut player_q: Query<(Entity, &EntityTilePos, &mut Direction, &mut HeldTimer), With<Player>>,
+    world_tiles: WorldTiles,
     mut ev_moveplayer: EventWriter<MoveEvent>,
     keeb: Res<Input<KeyCode>>,
+    time: Res<Time>,
 ) {
-    // find the dest_tile which is player_pos + direction pressed
-    let (player_entity, player_tile_pos, mut direction) = player_q.single_mut();
+    let (player_entity, player_tile_pos, mut direction, mut held_timer) = player_q.single_mut();
 
-    let mut dest_tile = Vec2::new(player_tile_pos.x as f32, player_tile_pos.y as f32);
-    // else if here prevents dest_tile equalling zero delta
-    if keeb.just_released(KeyCode::W) {
-        dest_tile.y += PLAYER_TILE_SPEED as f32;
-        *direction = Direction::Up;
-    } else if keeb.just_released(KeyCode::S) {
-        dest_tile.y -= PLAYER_TILE_SPEED as f32;
-        *direction = Direction::Down;
-    }
-    if keeb.just_released(KeyCode::D) {
-        dest_tile.x += PLAYER_TILE_SPEED as f32;
-        *direction = Direction::Right;
-    } else if keeb.just_released(KeyCode::A) {
-        dest_tile.x -= PLAYER_TILE_SPEED as f32;
-        *direction = Direction::Left;
-    }
-
-    // if the dest_tile or input was 0 then we don't do anything else
-    // make sure the position is not out of bounds of the map
-    if dest_tile == Vec2::new(player_tile_pos.x as f32, player_tile_pos.y as f32)
-        || !within_bounds(Vec2::new(dest_tile.x, dest_tile.y))
-    {
+    let pressed = DIRECTION_KEYS.iter().find(|(key, _)| keeb.just_pressed(*key));
+    let facing_held = DIRECTION_KEYS.iter().any(|(key, dir)| *dir == *direction && keeb.pressed(*key));
+    let step = if let Some((_, dir)) = pressed {
+        held_timer.reset();
+        *direction = *dir;
+        false
+    } else if facing_held && !held_timer.hold.finished() {
+        // The first step goes as soon as the press turns out to be a hold
+        held_timer.hold.tick(time.delta());
+        held_timer.hold.just_finished()
+    } else if facing_held {
+        held_timer.repeat.tick(time.delta());
+        held_timer.repeat.just_finished()
+    } else if let Some((_, dir)) = DIRECTION_KEYS.iter().find(|(key, _)| keeb.pressed(*key)) {
+        // Letting go of the last key falls back to one still held, which turns first like a fresh press
+        held_timer.reset();
+        *direction = *dir;
+        false
+    } else {
+        false
+    };
+    if !step {
         return;
     }
 
-    let dest_tile = TilePos {
-        x: dest_tile.x as u32,
-        y: dest_tile.y as u32,
+    // find the dest_tile which is player_pos + direction faced
+    let (dx, dy) = match *direction {
+        Direction::Up => (0, 1),
+        Direction::Down => (0, -1),
+        Direction::Left => (-1, 0),
+        Direction::Right => (1, 0),
+    };
+    let player_tile = TilePos {
+        x: player_tile_pos.x,
+        y: player_tile_pos.y,
+    };
+
+    // make sure the position is not past the last tile of the world
+    let dest_tile = match offset_tile(&player_tile, dx * PLAYER_TILE_SPEED, dy * PLAYER_TILE_SPEED) {
+        Some(tile_pos) => tile_pos,
+        None => return,
     };
 
-    // if the objects
-    for (_, size, blocking) in obj_tiles_q.iter().filter(|x| dest_tile.eq(x.0)) {
-        if size.is_some() || blocking.is_some() {
-            return;
-        }
+    // water on the ground or any sized object stops the player
+    if !world_tiles.walkable(&dest_tile) {
+        return;
     }
     ev_moveplayer.send(MoveEvent(player_entity, dest_tile));
 }
 
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
 fn player_harvest_action(
     player_q: Query<(Entity, &EntityTilePos, &Direction), With<Player>>,
-    blocking_interact_q: Query<(Entity, &TilePos), (With<Interact>, With<Blocking>)>,
-    obj_tiles_q: Query<(Entity, &ObjectSize, &TilePos)>,
+    world_tiles: WorldTiles,
+    blocking_interact_q: Query<(), (With<Interact>, With<Blocking>)>,
+    obj_sizes_q: Query<&ObjectSize>,
+    loose_interact_q: Query<(), (With<Interact>, Without<ObjectSize>)>,
     mut ev_interact: EventWriter<HarvestInteraction>,
+    build_mode: Res<BuildMode>,
     keeb: Res<Input<KeyCode>>,
 ) {
-    if !keeb.just_pressed(KeyCode::Space) {
+    // Space builds instead while in build mode
+    if !keeb.just_pressed(KeyCode::Space) || build_mode.is_building() {
         return;
     }
 
@@ -236,7 +283,11 @@ fn player_harvest_action(
     //   is the dest_tile part of a multi tile -> get owner entity
     //   is the owner entity in the interactable query -> get entity with components
     //   give entity to the interact system
-    if let Some((dest_entity, size, _)) = obj_tiles_q.iter().find(|x| dest_tile.eq(x.2)) {
+    let dest_entity = match world_tiles.obj_at(&dest_tile) {
+        Some(e) => e,
+        None => return,
+    };
+    if let Ok(size) = obj_sizes_q.get(dest_entity) {
         println!("hit something");
         match *size {
             ObjectSize::Single => {
@@ -247,7 +298,7 @@ fn player_harvest_action(
                 });
             }
             ObjectSize::Multi(owner) => {
-                if blocking_interact_q.get(owner).is_ok() {
+                if blocking_interact_q.contains(owner) {
                     ev_interact.send(HarvestInteraction {
                         harvester: player_entity,
                         harvested: owner,
@@ -256,5 +307,12 @@ fn player_harvest_action(
                 };
             }
         }
+    } else if loose_interact_q.contains(dest_entity) {
+        // flowers and such have no size, they are always their own owner
+        ev_interact.send(HarvestInteraction {
+            harvester: player_entity,
+            harvested: dest_entity,
+            reciever_pos: dest_tile,
+        });
     }
 }
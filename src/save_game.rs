@@ -0,0 +1,561 @@
+/// Save Game
+///
+/// Writes everything the world seed can not recreate to disk and restores it again
+/// A save only stores the seed plus what the player changed, the rest is regenerated on load
+use bevy::prelude::*;
+use bevy_ecs_tilemap::prelude::*;
+use iyes_loopless::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    cmp::Reverse,
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    assets::{FontAssets, SpriteAssets},
+    chest::Chest,
+    crafting::CraftingQueue,
+    entity_tile_pos::EntityTilePos,
+    interact::Health,
+    inventory::{Inventory, InventoryUpdate},
+    item_util::{Item, ItemDatabase, ItemId, ItemQuantity},
+    player::{Direction, Player},
+    spawn_point::NeedsSpawnPoint,
+    world_gen::{rebuild_world, GameWorld, WorldChanges, WorldSeed, WorldTilePos},
+    GameState,
+};
+
+// Bump this when the layout of SaveFile changes in a way `#[serde(default)]` can not cover
+// and add a migration for the old version
+const SAVE_VERSION: u32 = 1;
+const SAVE_DIR: &str = "saves";
+const QUICKSAVE_NAME: &str = "quicksave";
+
+pub struct SaveGamePlugin;
+
+impl Plugin for SaveGamePlugin {
+    fn build(&self, app: &mut App) {
+        // `--load <file>` continues a save on startup instead of a fresh world
+        if let Some(save) = save_from_args() {
+            app.insert_resource(WorldSeed(save.seed))
+                .insert_resource(PendingLoad(save));
+        }
+
+        app.add_enter_system(GameState::GameLoading, create_load_menu_ui)
+            .add_system(
+                apply_pending_load
+                    .run_in_state(GameState::Running)
+                    .run_if_resource_exists::<PendingLoad>()
+                    .before("chunks"),
+            )
+            .add_system(quicksave.run_not_in_state(GameState::AssetLoading))
+            .add_system(open_load_menu.run_in_state(GameState::Running))
+            .add_system(
+                load_menu_input
+                    .run_in_state(GameState::Menu)
+                    .run_if_resource_exists::<LoadMenu>(),
+            );
+    }
+}
+
+//=====> Save format
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    seed: u64,
+    removed_objs: Vec<SavedTile>,
+    damaged_objs: Vec<SavedHealth>,
+    ground_items: Vec<SavedGroundItem>,
+    // Chunk coordinates, not tiles
+    #[serde(default)]
+    visited_chunks: Vec<SavedTile>,
+    // The item each built obj was made from, always a single one
+    #[serde(default)]
+    placed_objs: Vec<SavedGroundItem>,
+    #[serde(default)]
+    containers: Vec<SavedContainer>,
+    player: SavedPlayer,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedTile {
+    x: u32,
+    y: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedHealth {
+    x: u32,
+    y: u32,
+    hp: i32,
+    // Older saves didn't keep it, 0 gets filled in with the object's own max once it is generated again
+    #[serde(default)]
+    max_hp: u32,
+}
+
+// Items keep their name next to the id so they can still be found if the ids get shuffled
+#[derive(Serialize, Deserialize)]
+struct SavedItem {
+    id: u32,
+    name: String,
+    amt: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedGroundItem {
+    x: u32,
+    y: u32,
+    item: SavedItem,
+}
+
+// What a built chest holds
+#[derive(Serialize, Deserialize)]
+struct SavedContainer {
+    x: u32,
+    y: u32,
+    items: Vec<SavedItem>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedPlayer {
+    x: u32,
+    y: u32,
+    direction: Direction,
+    inventory: Vec<SavedItem>,
+}
+
+impl SavedItem {
+    fn new(item_db: &ItemDatabase, id: ItemId, amt: ItemQuantity) -> SavedItem {
+        let name = match item_db.items.get(&id) {
+            Some(info) => info.name.clone(),
+            None => String::new(),
+        };
+        SavedItem {
+            id: id.0,
+            name,
+            amt: amt.0,
+        }
+    }
+
+    /// Finds the id the item has in the current database, the name wins when the saved id now
+    /// points to a different item
+    fn resolve(&self, item_db: &ItemDatabase) -> Option<ItemId> {
+        match item_db.items.get(&ItemId(self.id)) {
+            Some(info) if info.name == self.name => Some(info.id),
+            _ => item_db
+                .items
+                .values()
+                .find(|info| info.name == self.name)
+                .map(|info| info.id),
+        }
+    }
+}
+
+fn save_path(name: &str) -> PathBuf {
+    Path::new(SAVE_DIR).join(format!("{name}.json"))
+}
+
+fn write_save(path: &Path, save: &SaveFile) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(save)?)?;
+    Ok(())
+}
+
+/// Reads a save from disk, saves from a newer version of the game are refused
+fn read_save(path: &Path) -> Result<SaveFile, Box<dyn Error>> {
+    let save: SaveFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+    if save.version > SAVE_VERSION {
+        return Err(format!(
+            "save version {} is newer than this game supports ({SAVE_VERSION})",
+            save.version
+        )
+        .into());
+    }
+    Ok(save)
+}
+
+fn save_from_args() -> Option<SaveFile> {
+    let args: Vec<String> = env::args().collect();
+    let path = args
+        .iter()
+        .position(|arg| arg == "--load")
+        .and_then(|idx| args.get(idx + 1))?;
+    match read_save(Path::new(path)) {
+        Ok(save) => Some(save),
+        Err(err) => {
+            println!("Could not load save {path}, {err}");
+            None
+        }
+    }
+}
+
+//=====> Saving
+// F5 saves the game, it is also saved when quitting with escape
+#[allow(clippy::too_many_arguments)]
+fn quicksave(
+    player_q: Query<(&EntityTilePos, &Direction, &Inventory, &CraftingQueue), With<Player>>,
+    damaged_q: Query<(&Health, &WorldTilePos)>,
+    ground_items_q: Query<(&Item, &ItemQuantity, &WorldTilePos)>,
+    chests_q: Query<(&Inventory, &WorldTilePos), With<Chest>>,
+    changes: Option<Res<WorldChanges>>,
+    seed: Res<WorldSeed>,
+    item_db: Option<Res<ItemDatabase>>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::F5) && !keeb.just_pressed(KeyCode::Escape) {
+        return;
+    }
+    // Nothing to save until the world has been created
+    let (changes, item_db) = match (changes, item_db) {
+        (Some(changes), Some(item_db)) => (changes, item_db),
+        _ => return,
+    };
+    let (pos, direction, inventory, queue) = match player_q.get_single() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+
+    // Unloaded chunks keep their changes in WorldChanges, loaded chunks still have them in the world
+    let damaged_objs = changes
+        .damaged_objs
+        .iter()
+        .chain(damaged_q.iter().map(|(health, pos)| (&pos.0, health)))
+        .filter(|(_, health)| health.hp < health.max_hp as i32)
+        .map(|(pos, health)| SavedHealth {
+            x: pos.x,
+            y: pos.y,
+            hp: health.hp,
+            max_hp: health.max_hp,
+        })
+        .collect();
+    let ground_items = changes
+        .ground_items
+        .iter()
+        .map(|(pos, (id, qty))| (pos, *id, *qty))
+        .chain(ground_items_q.iter().map(|(item, qty, pos)| (&pos.0, item.id, *qty)))
+        .map(|(pos, id, qty)| SavedGroundItem {
+            x: pos.x,
+            y: pos.y,
+            item: SavedItem::new(&item_db, id, qty),
+        })
+        .collect();
+    let containers = changes
+        .containers
+        .iter()
+        .map(|(pos, contents)| (pos, contents.clone()))
+        .chain(
+            chests_q
+                .iter()
+                .map(|(inventory, pos)| (&pos.0, inventory.slots().flatten().collect())),
+        )
+        .map(|(pos, contents)| SavedContainer {
+            x: pos.x,
+            y: pos.y,
+            items: contents
+                .into_iter()
+                .map(|(id, amt)| SavedItem::new(&item_db, id, amt))
+                .collect(),
+        })
+        .collect();
+
+    let save = SaveFile {
+        version: SAVE_VERSION,
+        seed: seed.0,
+        removed_objs: changes
+            .removed_objs
+            .iter()
+            .map(|pos| SavedTile { x: pos.x, y: pos.y })
+            .collect(),
+        damaged_objs,
+        ground_items,
+        visited_chunks: changes
+            .visited_chunks
+            .iter()
+            .map(|chunk| SavedTile { x: chunk.x, y: chunk.y })
+            .collect(),
+        placed_objs: changes
+            .placed_objs
+            .iter()
+            .map(|(pos, id)| SavedGroundItem {
+                x: pos.x,
+                y: pos.y,
+                item: SavedItem::new(&item_db, *id, ItemQuantity(1)),
+            })
+            .collect(),
+        containers,
+        player: SavedPlayer {
+            x: pos.x,
+            y: pos.y,
+            direction: *direction,
+            // Jobs still in the queue are saved as the ingredients they took
+            inventory: inventory
+                .slots()
+                .flatten()
+                .chain(queue.reserved())
+                .map(|(id, amt)| SavedItem::new(&item_db, id, amt))
+                .collect(),
+        },
+    };
+
+    let path = save_path(QUICKSAVE_NAME);
+    match write_save(&path, &save) {
+        Ok(_) => println!("Saved game to {}", path.display()),
+        Err(err) => println!("Could not save the game, {err}"),
+    }
+}
+
+//=====> Loading
+/// A save waiting to be applied on top of a freshly generated world
+#[derive(Resource)]
+struct PendingLoad(SaveFile);
+
+// The world is regenerated from the save's seed first, this then restores what the player changed
+// before any chunk of the new world gets generated
+#[allow(clippy::type_complexity)]
+fn apply_pending_load(
+    mut commands: Commands,
+    mut player_q: Query<
+        (
+            Entity,
+            &mut EntityTilePos,
+            &mut Direction,
+            &mut Inventory,
+            &mut CraftingQueue,
+            &mut Transform,
+        ),
+        With<Player>,
+    >,
+    mut changes: ResMut<WorldChanges>,
+    pending: Res<PendingLoad>,
+    item_db: Res<ItemDatabase>,
+    mut ev_invupdate: EventWriter<InventoryUpdate>,
+) {
+    let save = &pending.0;
+
+    *changes = WorldChanges::default();
+    for tile in save.removed_objs.iter() {
+        changes.removed_objs.insert(TilePos { x: tile.x, y: tile.y });
+    }
+    for chunk in save.visited_chunks.iter() {
+        changes.visited_chunks.insert(UVec2::new(chunk.x, chunk.y));
+    }
+    for damaged in save.damaged_objs.iter() {
+        let mut health = Health::new(damaged.max_hp);
+        health.hp = damaged.hp;
+        changes.damaged_objs.insert(
+            TilePos {
+                x: damaged.x,
+                y: damaged.y,
+            },
+            health,
+        );
+    }
+    for ground_item in save.ground_items.iter() {
+        match ground_item.item.resolve(&item_db) {
+            Some(id) => {
+                let pos = TilePos {
+                    x: ground_item.x,
+                    y: ground_item.y,
+                };
+                changes
+                    .ground_items
+                    .insert(pos, (id, ItemQuantity(ground_item.item.amt)));
+            }
+            None => println!("Dropped unknown item {} from the save", ground_item.item.name),
+        }
+    }
+    for placed in save.placed_objs.iter() {
+        match placed.item.resolve(&item_db) {
+            Some(id) => {
+                changes.placed_objs.insert(
+                    TilePos {
+                        x: placed.x,
+                        y: placed.y,
+                    },
+                    id,
+                );
+            }
+            None => println!("Dropped unknown built {} from the save", placed.item.name),
+        }
+    }
+    for container in save.containers.iter() {
+        let mut contents = Vec::new();
+        for saved in container.items.iter() {
+            match saved.resolve(&item_db) {
+                Some(id) => contents.push((id, ItemQuantity(saved.amt))),
+                None => println!("Dropped unknown item {} from the save", saved.name),
+            }
+        }
+        changes.containers.insert(
+            TilePos {
+                x: container.x,
+                y: container.y,
+            },
+            contents,
+        );
+    }
+
+    if let Ok((player, mut pos, mut direction, mut inventory, mut queue, mut transform)) = player_q.get_single_mut() {
+        *pos = EntityTilePos {
+            x: save.player.x,
+            y: save.player.y,
+        };
+        // Where the player was saved wins over looking for a spawn
+        commands.entity(player).remove::<NeedsSpawnPoint>();
+        *direction = save.player.direction;
+        // Snap the sprite so it does not slide across the whole map
+        let world_pos = pos.center_in_world();
+        transform.translation = world_pos.extend(transform.translation.z);
+
+        // The save already holds what queued jobs took as inventory, so the jobs have to go
+        *queue = CraftingQueue::default();
+        inventory.clear();
+        for saved in save.player.inventory.iter() {
+            match saved.resolve(&item_db) {
+                Some(id) => {
+                    let left = inventory.add_item(id, &ItemQuantity(saved.amt), &item_db);
+                    if left.0 > 0 {
+                        println!("No room for {} {} from the save", left.0, saved.name);
+                    }
+                }
+                None => println!("Dropped unknown item {} from the save", saved.name),
+            }
+        }
+        ev_invupdate.send(InventoryUpdate);
+    }
+
+    commands.remove_resource::<PendingLoad>();
+    println!("Loaded save with seed {}", save.seed);
+}
+
+/// Open while the player is picking a save to load
+#[derive(Resource)]
+struct LoadMenu {
+    saves: Vec<PathBuf>,
+    selected: usize,
+}
+
+#[derive(Component)]
+struct LoadMenuUi;
+
+fn create_load_menu_ui(mut commands: Commands, font: Res<FontAssets>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.chunk.clone(),
+                font_size: 30.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.),
+                top: Val::Px(40.),
+                ..default()
+            },
+            ..default()
+        }),
+        LoadMenuUi,
+    ));
+}
+
+// Saves in the save folder, newest first
+fn list_saves() -> Vec<PathBuf> {
+    let entries = match fs::read_dir(SAVE_DIR) {
+        Ok(entries) => entries,
+        Err(_) => return Vec::new(),
+    };
+    let mut saves: Vec<(PathBuf, std::time::SystemTime)> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let modified = fs::metadata(&path).and_then(|meta| meta.modified()).ok()?;
+            Some((path, modified))
+        })
+        .collect();
+    saves.sort_by_key(|(_, modified)| Reverse(*modified));
+    saves.into_iter().map(|(path, _)| path).collect()
+}
+
+fn render_load_menu(menu: &LoadMenu) -> String {
+    if menu.saves.is_empty() {
+        return "No saves found".to_string();
+    }
+    let mut text = "Load game".to_string();
+    for (i, path) in menu.saves.iter().enumerate() {
+        let name = path.file_stem().map(|stem| stem.to_string_lossy()).unwrap_or_default();
+        let cursor = if i == menu.selected { ">" } else { " " };
+        text.push_str(&format!("\n{cursor} {name}"));
+    }
+    text
+}
+
+// F9 opens the menu of saves that can be loaded
+fn open_load_menu(
+    mut commands: Commands,
+    mut menu_ui_q: Query<&mut Text, With<LoadMenuUi>>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::F9) {
+        return;
+    }
+
+    let menu = LoadMenu {
+        saves: list_saves(),
+        selected: 0,
+    };
+    if let Ok(mut text) = menu_ui_q.get_single_mut() {
+        text.sections[0].value = render_load_menu(&menu);
+    }
+    commands.insert_resource(menu);
+    commands.insert_resource(NextState(GameState::Menu));
+}
+
+// W/S picks a save, Enter loads it and F9 backs out of the menu
+fn load_menu_input(
+    mut commands: Commands,
+    mut menu: ResMut<LoadMenu>,
+    mut seed: ResMut<WorldSeed>,
+    mut menu_ui_q: Query<&mut Text, With<LoadMenuUi>>,
+    world: Res<GameWorld>,
+    sprites: Res<SpriteAssets>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if keeb.just_pressed(KeyCode::S) && menu.selected + 1 < menu.saves.len() {
+        menu.selected += 1;
+    } else if keeb.just_pressed(KeyCode::W) && menu.selected > 0 {
+        menu.selected -= 1;
+    }
+
+    let mut close = keeb.just_pressed(KeyCode::F9);
+    if keeb.just_pressed(KeyCode::Return) {
+        if let Some(path) = menu.saves.get(menu.selected) {
+            match read_save(path) {
+                Ok(save) => {
+                    // The new world spawns at the end of this frame so the save is applied after it
+                    seed.0 = save.seed;
+                    rebuild_world(&mut commands, &world, &sprites, save.seed, WorldChanges::default());
+                    commands.insert_resource(PendingLoad(save));
+                    close = true;
+                }
+                Err(err) => println!("Could not load save {}, {err}", path.display()),
+            }
+        }
+    }
+
+    if let Ok(mut text) = menu_ui_q.get_single_mut() {
+        text.sections[0].value = if close { String::new() } else { render_load_menu(&menu) };
+    }
+    if close {
+        commands.remove_resource::<LoadMenu>();
+        commands.insert_resource(NextState(GameState::Running));
+    }
+}
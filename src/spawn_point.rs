@@ -0,0 +1,123 @@
+/// Spawn Point
+///
+/// Players start on the free tile closest to the world origin, as long as it isn't cut off
+/// on a tiny island or boxed in by trees. Happens for a new game and again whenever the world is regenerated
+use bevy::{prelude::*, utils::HashSet};
+use bevy_ecs_tilemap::prelude::*;
+use iyes_loopless::prelude::*;
+use std::collections::VecDeque;
+
+use crate::{
+    entity_tile_pos::EntityTilePos,
+    player::{Player, PLAYER_Z},
+    world_gen::{straight_neighbours, tiles_around, WorldTiles, WORLD_ORIGIN},
+    GameState,
+};
+
+const SPAWN_SEARCH_RADIUS: i32 = 48; // Stays inside the chunks generated around the preferred spot
+const MIN_SPAWN_REGION: usize = 200; // Walkable tiles a spawn has to reach to not count as cut off
+
+pub struct SpawnPointPlugin;
+
+impl Plugin for SpawnPointPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(find_spawn_point.run_in_state(GameState::Running).after("chunks"));
+    }
+}
+
+/// The player still has to be moved off whatever they were put on, their position is where the search starts
+#[derive(Component)]
+pub struct NeedsSpawnPoint;
+
+/// Where the search for a spawn starts
+pub fn preferred_spawn() -> EntityTilePos {
+    EntityTilePos {
+        x: WORLD_ORIGIN,
+        y: WORLD_ORIGIN,
+    }
+}
+
+// Waits for the chunks around the player to be generated, then moves them onto the spawn
+#[allow(clippy::type_complexity)]
+fn find_spawn_point(
+    mut commands: Commands,
+    mut player_q: Query<(Entity, &mut EntityTilePos, &mut Transform), (With<Player>, With<NeedsSpawnPoint>)>,
+    world_tiles: WorldTiles,
+    spawned_tiles_q: Query<(), With<TilePos>>,
+) {
+    let (player, mut pos, mut transform) = match player_q.get_single_mut() {
+        Ok(e) => e,
+        Err(_) => return,
+    };
+    let preferred = TilePos { x: pos.x, y: pos.y };
+    // Tiles get their components at the end of the frame they were generated in
+    match world_tiles.ground_at(&preferred) {
+        Some(ground) if spawned_tiles_q.contains(ground) => {}
+        _ => return,
+    }
+
+    let walkable = |tile_pos: &TilePos| world_tiles.walkable(tile_pos);
+    let free = |tile_pos: &TilePos| walkable(tile_pos) && world_tiles.obj_at(tile_pos).is_none();
+
+    commands.entity(player).remove::<NeedsSpawnPoint>();
+    match find_spawn(preferred, walkable, free) {
+        Some(spawn) => {
+            pos.x = spawn.x;
+            pos.y = spawn.y;
+            transform.translation = pos.center_in_world().extend(PLAYER_Z);
+            println!("Spawned player at {} {}", spawn.x, spawn.y);
+        }
+        None => println!("No free tile to spawn on, staying at {} {}", pos.x, pos.y),
+    }
+}
+
+/// The free tile closest to the preferred one that reaches enough walkable tiles,
+/// when none does the one reaching the most is used instead
+fn find_spawn(
+    preferred: TilePos,
+    walkable: impl Fn(&TilePos) -> bool,
+    free: impl Fn(&TilePos) -> bool,
+) -> Option<TilePos> {
+    // Tiles of regions already found to be too small, no need to fill them again
+    let mut small_regions: HashSet<TilePos> = HashSet::new();
+    let mut best: Option<(usize, TilePos)> = None;
+
+    for candidate in tiles_around(preferred, SPAWN_SEARCH_RADIUS) {
+        if small_regions.contains(&candidate) || !free(&candidate) {
+            continue;
+        }
+
+        let region = walkable_region(candidate, &walkable, MIN_SPAWN_REGION);
+        if region.len() >= MIN_SPAWN_REGION {
+            return Some(candidate);
+        }
+        if best.map_or(0, |(size, _)| size) < region.len() {
+            best = Some((region.len(), candidate));
+        }
+        small_regions.extend(region);
+    }
+
+    best.map(|(_, spawn)| spawn)
+}
+
+// Fills out from the start over walkable tiles, stops once it has found the limit
+fn walkable_region(start: TilePos, walkable: impl Fn(&TilePos) -> bool, limit: usize) -> Vec<TilePos> {
+    let mut region = vec![start];
+    let mut seen: HashSet<TilePos> = HashSet::from_iter([start]);
+    let mut queue = VecDeque::from([start]);
+
+    while let Some(tile_pos) = queue.pop_front() {
+        // Straight neighbours only, the player can't move diagonally
+        for neighbour in straight_neighbours(tile_pos) {
+            if region.len() >= limit {
+                return region;
+            }
+            if seen.insert(neighbour) && walkable(&neighbour) {
+                region.push(neighbour);
+                queue.push_back(neighbour);
+            }
+        }
+    }
+
+    region
+}
@@ -6,201 +6,608 @@
  *
  */
 use bracket_noise::prelude::*;
-use rand::Rng;
-use std::time::Instant;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::env;
 
-use bevy::prelude::*;
+use bevy::{
+    ecs::system::{SystemParam, SystemState},
+    prelude::*,
+    utils::{HashMap, HashSet},
+};
 use bevy_ecs_tilemap::prelude::*;
 use iyes_loopless::prelude::*;
 
 use crate::{
-    assets::SpriteAssets, comfort_config::load_settings, constants::world_obj_sprites::*, interact::*, GameState,
+    assets::{FontAssets, SpriteAssets},
+    building::Built,
+    chest::{chest_inventory, Chest, CHEST_SLOTS},
+    comfort_config::{load_config, BiomeSettings, ComfortConfig, FractalSettings},
+    constants::world_obj_sprites::*,
+    entity_tile_pos::EntityTilePos,
+    interact::*,
+    inventory::Inventory,
+    item_util::{Item, ItemDatabase, ItemId, ItemQuantity, Placeable, ToolKind},
+    player::Player,
+    spawn_point::{preferred_spawn, NeedsSpawnPoint},
+    GameState,
 };
 
-pub const MAP_SIZE_X: u32 = 128; // Size of map currently only supports square maps
-pub const MAP_SIZE_Y: u32 = 128; // Size of map currently only supports square maps
+// Tiles are counted from the middle of u32 so the world goes on in every direction from the spawn,
+// noise and pixel positions work from the offset to it so they stay small enough for f32
+pub const WORLD_ORIGIN: u32 = 1 << 31;
+pub const CHUNK_SIZE: u32 = 32; // Tiles along a side of a chunk, kept even so trees never straddle two chunks
+const ORIGIN_CHUNK: u32 = WORLD_ORIGIN / CHUNK_SIZE;
+const CHUNK_LOAD_RADIUS: u32 = 2; // Chunks around the player's chunk that are kept generated
+const CHUNK_UNLOAD_RADIUS: u32 = 3; // Bigger than the load radius so walking along a chunk border doesn't thrash
 pub const TILE_PIXELS_X: f32 = 8f32;
 pub const TILE_PIXELS_Y: f32 = 8f32;
 pub const FLOOR_Z: f32 = 0f32; // Generally the lowest depth in terms of sprites
 pub const OBJECT_Z: f32 = 10f32; // Height for objects such as trees or rocks to exist in the world
 pub const ITEM_Z: f32 = 5f32; // Height for items
+const TREE_HP: u32 = 5;
+const ROCK_HP: u32 = 6;
+const MOUND_HP: u32 = 3;
 
 pub struct WorldGenerationPlugin;
 
 impl Plugin for WorldGenerationPlugin {
     fn build(&self, app: &mut App) {
-        app.add_enter_system(GameState::GameLoading, create_world.label("map"))
+        app.insert_resource(WorldSeed::from_env())
+            .add_event::<RegenerateWorldEvent>()
+            .add_enter_system(GameState::GameLoading, create_world.label("map"))
+            .add_enter_system(GameState::GameLoading, create_seed_prompt_ui)
             .add_system_set(
                 ConditionSet::new()
                     .run_in_state(GameState::Running)
-                    .with_system(regenerate_world)
+                    .with_system(reroll_world_seed)
+                    .with_system(open_seed_prompt)
                     .with_system(stretch_tree)
                     .into(),
+            )
+            // Before regenerating so tiles streamed in this frame are part of the world that gets despawned
+            .add_system(
+                stream_chunks
+                    .run_in_state(GameState::Running)
+                    .label("chunks")
+                    .before("regenerate"),
+            )
+            .add_system(
+                regenerate_world
+                    .run_on_event::<RegenerateWorldEvent>()
+                    .label("regenerate"),
+            )
+            .add_system(record_removed_objs.run_on_event::<HealthBelowZeroEvent>())
+            .add_system(
+                seed_prompt_input
+                    .run_in_state(GameState::Menu)
+                    .run_if_resource_exists::<SeedPrompt>(),
             );
     }
 }
 
+/// The seed every random decision in world generation is derived from, sharing it reproduces the world
+#[derive(Resource, Clone, Copy)]
+pub struct WorldSeed(pub u64);
+
+impl WorldSeed {
+    /// Takes the seed from `--seed <number>`, then from config/worldgen.toml, otherwise picks a random one
+    pub fn from_env() -> WorldSeed {
+        let args: Vec<String> = env::args().collect();
+        if let Some(idx) = args.iter().position(|arg| arg == "--seed") {
+            match args.get(idx + 1).map(|seed| seed.parse::<u64>()) {
+                Some(Ok(seed)) => return WorldSeed(seed),
+                _ => println!("--seed expects a positive number, ignoring it"),
+            }
+        }
+
+        match load_config() {
+            Ok(config) => {
+                if let Some(seed) = config.world.seed {
+                    return WorldSeed(seed);
+                }
+            }
+            Err(err) => println!("Could not read the world seed from config, {err}"),
+        }
+
+        WorldSeed(rand::random::<u64>())
+    }
+}
+
+/// Despawns the current world and builds a new one from the `WorldSeed`
+pub struct RegenerateWorldEvent;
+
+/// Tiles of the generated world that the player has changed, the seed alone can not recreate these
+/// Loaded chunks keep their objects and items in the tilemaps, they move in here when the chunk unloads
+#[derive(Resource, Default)]
+pub struct WorldChanges {
+    // Base tile of world objects that have been destroyed
+    pub removed_objs: HashSet<TilePos>,
+    // Damaged world objects in chunks that are not loaded
+    pub damaged_objs: HashMap<TilePos, Health>,
+    // Items lying on the ground in chunks that are not loaded
+    pub ground_items: HashMap<TilePos, (ItemId, ItemQuantity)>,
+    // Chunks that were generated before, their biome items were already handed out
+    pub visited_chunks: HashSet<UVec2>,
+    // Everything the player built and the item it was built from, loaded or not
+    pub placed_objs: HashMap<TilePos, ItemId>,
+    // What the chests in chunks that are not loaded hold
+    pub containers: HashMap<TilePos, Vec<(ItemId, ItemQuantity)>>,
+}
+
+/// The overworld, made of chunks that get generated from the seed as the player comes close
+/// Every chunk has its own tilemaps so there is no edge to the world, only chunks that were not generated yet
+#[derive(Resource)]
 pub struct GameWorld {
+    chunks: HashMap<UVec2, ChunkTilemaps>,
+    terrain: Handle<Image>,
+    world_objs: Handle<Image>,
+    items: Handle<Image>,
+    terrain_noise: FastNoise,
+    tree_noise: FastNoise,
+    temperature_noise: FastNoise,
+    moisture_noise: FastNoise,
+    // The last biome matches everything, so every tile has one
+    biomes: Vec<BiomeSettings>,
+    seed: u64,
+}
+
+/// The tilemaps of a generated chunk, their storages are indexed by where a tile is inside of the chunk
+#[derive(Clone, Copy)]
+pub struct ChunkTilemaps {
     // Floor tiles are the underlying tiles to everything in the overworld, should NEVER be empty,
     // limited to the terrain atlas
-    floor_tiles: TileStorage,
-    floor_tilemap: Entity,
+    pub floor: Entity,
     // Objs tiles are the items, world objects, special terrain features that appear on top of the floor
     // limited to the world_objs atlas
+    pub objs: Entity,
+    // Items lying around in the overworld, limited to the items atlas
+    pub items: Entity,
+}
+
+#[derive(Clone, Copy)]
+pub enum Layer {
+    Floor,
+    Objs,
+    Items,
+}
+
+impl ChunkTilemaps {
+    pub fn get(&self, layer: Layer) -> Entity {
+        match layer {
+            Layer::Floor => self.floor,
+            Layer::Objs => self.objs,
+            Layer::Items => self.items,
+        }
+    }
+}
+
+/// Fills in a single chunk of the world, the tiles go into storages of its own that become
+/// the chunk's tilemaps once it is finished
+struct ChunkBuilder<'a> {
+    world: &'a GameWorld,
+    chunk: UVec2,
+    tilemaps: ChunkTilemaps,
+    floor_tiles: TileStorage,
     objs_tiles: TileStorage,
-    objs_tilemap: Entity,
+    item_tiles: TileStorage,
+    changes: &'a mut WorldChanges,
     // TilePos that cannot have anything else placed ontop of them
-    blocked_tiles: Vec<TilePos>,
+    blocked_tiles: HashSet<TilePos>,
+    // Index into the world's biomes for every tile of the chunk
+    biome_map: HashMap<TilePos, usize>,
+}
+
+fn create_world(mut commands: Commands, tiles: Res<SpriteAssets>, seed: Res<WorldSeed>) {
+    spawn_world(
+        &mut commands,
+        tiles.terrain.clone(),
+        tiles.world_objs.clone(),
+        tiles.items.clone(),
+        seed.0,
+        world_config(),
+        WorldChanges::default(),
+    );
+}
+
+/// The worldgen config for a new world, a broken config is reported and the built-in settings are used instead
+fn world_config() -> ComfortConfig {
+    match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{err}");
+            println!("Using the built-in world generation settings");
+            ComfortConfig::default()
+        }
+    }
+}
+
+/// Sets up a new world without any chunks, `stream_chunks` generates them around the player
+/// `changes` are what the new world starts out with, a fresh world has not been touched yet
+fn spawn_world(
+    commands: &mut Commands,
+    terrain: Handle<Image>,
+    world_objs: Handle<Image>,
+    items: Handle<Image>,
     seed: u64,
+    config: ComfortConfig,
+    changes: WorldChanges,
+) {
+    let mut biomes = config.biome;
+    biomes.push(BiomeSettings::default());
+
+    commands.insert_resource(GameWorld {
+        chunks: HashMap::new(),
+        terrain,
+        world_objs,
+        items,
+        terrain_noise: fractal_perlin(&config.terrainperlin, seed),
+        tree_noise: fractal_perlin(&config.treeperlin, seed),
+        // Offset seeds so the biome noises don't line up with each other when their settings match
+        temperature_noise: fractal_perlin(&config.temperatureperlin, seed.wrapping_add(1)),
+        moisture_noise: fractal_perlin(&config.moistureperlin, seed.wrapping_add(2)),
+        biomes,
+        seed,
+    });
+    commands.insert_resource(changes);
+
+    println!("World created succesfully with seed {seed}");
 }
 
-fn create_world(mut commands: Commands, tiles: Res<SpriteAssets>) {
-    let start = Instant::now();
-    let tilemap_size = world_size();
+// Sits at the chunk's first tile, so a tile's TilePos is where it is inside of the chunk
+fn chunk_tilemap(texture: Handle<Image>, chunk: UVec2, z: f32, storage: TileStorage) -> TilemapBundle {
+    let origin = chunk * CHUNK_SIZE;
+    TilemapBundle {
+        grid_size: tilegridsize_pixels(),
+        map_type: TilemapType::Square,
+        size: chunk_size(),
+        storage,
+        texture: TilemapTexture::Single(texture),
+        tile_size: tilemaptilesize_pixels(),
+        transform: Transform::from_translation(tile_to_world(origin.x, origin.y).extend(z)),
+        ..Default::default()
+    }
+}
 
-    let mut overworld = GameWorld {
-        floor_tiles: TileStorage::empty(tilemap_size),
-        floor_tilemap: commands.spawn_empty().id(),
-        objs_tiles: TileStorage::empty(tilemap_size),
-        objs_tilemap: commands.spawn_empty().id(),
-        blocked_tiles: Vec::new(),
-        // TODO: allow user to input a seed, maybe using a config file?
-        seed: rand::random::<u64>(),
+/// Generates the chunks around the player and unloads the ones they walked away from,
+/// anything the player changed in an unloaded chunk is kept in `WorldChanges`
+#[allow(clippy::too_many_arguments)]
+fn stream_chunks(
+    mut commands: Commands,
+    mut world: ResMut<GameWorld>,
+    mut changes: ResMut<WorldChanges>,
+    tile_storage_q: Query<&TileStorage>,
+    player_q: Query<&EntityTilePos, With<Player>>,
+    health_q: Query<&Health>,
+    chests_q: Query<&Inventory, With<Chest>>,
+    items_q: Query<(&Item, &ItemQuantity)>,
+    item_db: Res<ItemDatabase>,
+) {
+    let player_pos = match player_q.get_single() {
+        Ok(pos) => pos,
+        Err(_) => return,
     };
+    let player_chunk = chunk_of(player_pos.x, player_pos.y);
 
-    // Spawn the elements of the tilemaps.
-    overworld
-        .spawn_terrain(&mut commands)
-        .spawn_trees(&mut commands)
-        .spawn_flowers(&mut commands);
-
-    commands.entity(overworld.floor_tilemap).insert((
-        TilemapBundle {
-            grid_size: tilegridsize_pixels(),
-            map_type: TilemapType::Square,
-            size: tilemap_size,
-            storage: overworld.floor_tiles,
-            texture: TilemapTexture::Single(tiles.terrain.clone()),
-            tile_size: tilemaptilesize_pixels(),
-            transform: Transform::from_translation(Vec3::new(0f32, 0f32, FLOOR_Z)),
-            ..Default::default()
-        },
-        GroundStorage,
-    ));
-    commands.entity(overworld.objs_tilemap).insert((
-        TilemapBundle {
-            grid_size: tilegridsize_pixels(),
-            map_type: TilemapType::Square,
-            size: tilemap_size,
-            storage: overworld.objs_tiles,
-            texture: TilemapTexture::Single(tiles.world_objs.clone()),
-            tile_size: tilemaptilesize_pixels(),
-            transform: Transform::from_translation(Vec3::new(0f32, 0f32, OBJECT_Z)),
-            ..Default::default()
-        },
-        ObjectStorage,
-        // Blocking,
-    ));
+    let far_chunks: Vec<UVec2> = world
+        .chunks
+        .keys()
+        .filter(|chunk| chunk_distance(**chunk, player_chunk) > CHUNK_UNLOAD_RADIUS)
+        .copied()
+        .collect();
+    for chunk in far_chunks {
+        let tilemaps = match world.chunks.remove(&chunk) {
+            Some(tilemaps) => tilemaps,
+            None => continue,
+        };
+        if let Ok([objs_tiles, item_tiles]) = tile_storage_q.get_many([tilemaps.objs, tilemaps.items]) {
+            for tile_pos in chunk_tiles(chunk) {
+                let local = local_pos(&tile_pos);
+                if let Some(obj) = objs_tiles.get(&local) {
+                    if let Ok(health) = health_q.get(obj) {
+                        if health.hp < health.max_hp as i32 {
+                            changes.damaged_objs.insert(tile_pos, *health);
+                        }
+                    }
+                    if let Ok(inventory) = chests_q.get(obj) {
+                        changes
+                            .containers
+                            .insert(tile_pos, inventory.slots().flatten().collect());
+                    }
+                }
+                if let Some(item_entity) = item_tiles.get(&local) {
+                    if let Ok((item, qty)) = items_q.get(item_entity) {
+                        changes.ground_items.insert(tile_pos, (item.id, *qty));
+                    }
+                }
+            }
+        }
+        despawn_chunk(&mut commands, &tilemaps);
+    }
 
-    create_item_tilestorage(commands, tiles);
+    for chunk in chunks_around(player_chunk, CHUNK_LOAD_RADIUS) {
+        if world.chunks.contains_key(&chunk) {
+            continue;
+        }
 
-    let duration = start.elapsed();
-    println!("World created succesfully in {:?}", duration);
+        let mut biome_items = Vec::new();
+        let mut builder = ChunkBuilder::new(&world, chunk, &mut changes, &mut commands);
+        builder
+            .spawn_terrain(&mut commands)
+            .spawn_built(&mut commands, &item_db)
+            .spawn_trees(&mut commands)
+            .spawn_rocks(&mut commands)
+            .spawn_mounds(&mut commands)
+            .spawn_flowers(&mut commands)
+            .spawn_items(&mut biome_items)
+            .spawn_ground_items(&mut commands, &item_db, &biome_items);
+        let tilemaps = builder.finish(&mut commands);
+        world.chunks.insert(chunk, tilemaps);
+        changes.visited_chunks.insert(chunk);
+    }
 }
 
-fn regenerate_world(
-    mut commands: Commands,
-    mut tile_storage_q: Query<(&mut TileStorage, Entity)>,
+// Remembers which world objects were destroyed so they stay gone when their chunk is generated again
+fn record_removed_objs(mut ev_killed: EventReader<HealthBelowZeroEvent>, mut changes: ResMut<WorldChanges>) {
+    for ev in ev_killed.iter() {
+        changes.removed_objs.insert(ev.1);
+    }
+}
+
+// Rolls a brand new seed for the world
+fn reroll_world_seed(
+    mut seed: ResMut<WorldSeed>,
+    mut ev_regenerate: EventWriter<RegenerateWorldEvent>,
     keeb: Res<Input<KeyCode>>,
-    sprites: Res<SpriteAssets>,
 ) {
     if !keeb.just_pressed(KeyCode::Grave) {
         return;
     }
 
-    let mut i: i32 = 1;
-    for (mut tile_storage, tilemap_entity) in tile_storage_q.iter_mut() {
-        // Despawn existing world
-        for x in 0..MAP_SIZE_X {
-            for y in 0..MAP_SIZE_Y {
-                let tile_pos = TilePos { x, y };
-                if let Some(tile_entity) = tile_storage.get(&tile_pos) {
-                    commands.entity(tile_entity).despawn_recursive();
-                    tile_storage.remove(&tile_pos);
-                }
-            }
-        }
-        commands.entity(tilemap_entity).despawn_recursive();
-        println!("Despawned storage {i}");
-        i += 1;
+    seed.0 = rand::random::<u64>();
+    ev_regenerate.send(RegenerateWorldEvent);
+}
+
+#[allow(clippy::too_many_arguments)]
+fn regenerate_world(
+    mut commands: Commands,
+    world: Res<GameWorld>,
+    mut changes: ResMut<WorldChanges>,
+    chests_q: Query<(&Inventory, &WorldTilePos), With<Chest>>,
+    mut player_q: Query<(Entity, &mut EntityTilePos), With<Player>>,
+    ev_regenerate: EventReader<RegenerateWorldEvent>,
+    sprites: Res<SpriteAssets>,
+    seed: Res<WorldSeed>,
+) {
+    // Multiple requests in a frame still only need one new world
+    ev_regenerate.clear();
+    // Builds are carried over into the new world, chests keep what they held
+    let mut containers = std::mem::take(&mut changes.containers);
+    for (inventory, pos) in chests_q.iter() {
+        containers.insert(pos.0, inventory.slots().flatten().collect());
+    }
+    let kept = WorldChanges {
+        placed_objs: std::mem::take(&mut changes.placed_objs),
+        containers,
+        ..default()
+    };
+    rebuild_world(&mut commands, &world, &sprites, seed.0, kept);
+    // The new world has a new spawn, whatever the player stood on before is gone
+    if let Ok((player, mut pos)) = player_q.get_single_mut() {
+        *pos = preferred_spawn();
+        commands.entity(player).insert(NeedsSpawnPoint);
     }
+}
+
+/// Despawns every chunk of the current world and spawns a new world from the seed in its place
+/// The new world starts out with `kept` as its changes
+pub fn rebuild_world(
+    commands: &mut Commands,
+    world: &GameWorld,
+    sprites: &SpriteAssets,
+    seed: u64,
+    kept: WorldChanges,
+) {
+    // Chunks generated this frame are already in here even though their tilemaps aren't spawned yet
+    for tilemaps in world.chunks.values() {
+        despawn_chunk(commands, tilemaps);
+    }
+    println!("Despawned {} chunks", world.chunks.len());
 
     // Create world
-    create_world(commands, sprites);
+    spawn_world(
+        commands,
+        sprites.terrain.clone(),
+        sprites.world_objs.clone(),
+        sprites.items.clone(),
+        seed,
+        world_config(),
+        kept,
+    );
 }
 
-impl GameWorld {
+/// Generates the chunks into a bare ECS world without a window or any game systems, used by the worldgen CLI
+/// The floor and objects end up as tiles like in game, the biome items are handed back instead of spawned
+pub fn generate_headless(
+    ecs_world: &mut World,
+    seed: u64,
+    config: ComfortConfig,
+    chunks: &[UVec2],
+) -> Vec<(TilePos, ItemId)> {
+    let mut spawn_state: SystemState<Commands> = SystemState::new(ecs_world);
+    let mut commands = spawn_state.get_mut(ecs_world);
+    spawn_world(&mut commands, default(), default(), default(), seed, config, default());
+    spawn_state.apply(ecs_world);
+
+    let mut build_state: SystemState<(Commands, ResMut<GameWorld>, ResMut<WorldChanges>)> = SystemState::new(ecs_world);
+    let (mut commands, mut world, mut changes) = build_state.get_mut(ecs_world);
+
+    let mut biome_items = Vec::new();
+    for chunk in chunks {
+        let mut builder = ChunkBuilder::new(&world, *chunk, &mut changes, &mut commands);
+        builder
+            .spawn_terrain(&mut commands)
+            .spawn_trees(&mut commands)
+            .spawn_rocks(&mut commands)
+            .spawn_mounds(&mut commands)
+            .spawn_flowers(&mut commands)
+            .spawn_items(&mut biome_items);
+        let tilemaps = builder.finish(&mut commands);
+        world.chunks.insert(*chunk, tilemaps);
+    }
+    build_state.apply(ecs_world);
+
+    biome_items
+}
+
+impl<'a> ChunkBuilder<'a> {
+    fn new(
+        world: &'a GameWorld,
+        chunk: UVec2,
+        changes: &'a mut WorldChanges,
+        commands: &mut Commands,
+    ) -> ChunkBuilder<'a> {
+        let biome_map = chunk_tiles(chunk)
+            .map(|tile_pos| (tile_pos, world.biome_index(&tile_pos)))
+            .collect();
+        // The tiles need their tilemap before it is done, so the entities are taken up front
+        let tilemaps = ChunkTilemaps {
+            floor: commands.spawn_empty().id(),
+            objs: commands.spawn_empty().id(),
+            items: commands.spawn_empty().id(),
+        };
+        ChunkBuilder {
+            world,
+            chunk,
+            tilemaps,
+            floor_tiles: TileStorage::empty(chunk_size()),
+            objs_tiles: TileStorage::empty(chunk_size()),
+            item_tiles: TileStorage::empty(chunk_size()),
+            changes,
+            blocked_tiles: HashSet::new(),
+            biome_map,
+        }
+    }
+
+    fn biome(&self, tile_pos: &TilePos) -> &'a BiomeSettings {
+        &self.world.biomes[self.biome_map[tile_pos]]
+    }
+
+    /// Each generation step of each chunk gets its own rng, so neither the order chunks load in
+    /// nor changing one step reshuffles the rest of the world
+    fn seeded_rng(&self, step: u64) -> StdRng {
+        let (x, y) = (
+            self.chunk.x.wrapping_sub(ORIGIN_CHUNK),
+            self.chunk.y.wrapping_sub(ORIGIN_CHUNK),
+        );
+        let chunk_id = ((x as u64) << 32) | y as u64;
+        StdRng::seed_from_u64(self.world.seed ^ chunk_id.wrapping_mul(0x9E37_79B9_7F4A_7C15) ^ step)
+    }
+
+    /// Puts back the health an obj was left with, saves that didn't keep the max get the obj's own
+    fn restore_damage(&mut self, commands: &mut Commands, obj: Entity, tile_pos: &TilePos, max_hp: u32) {
+        if let Some(mut health) = self.changes.damaged_objs.remove(tile_pos) {
+            if health.max_hp == 0 {
+                health.max_hp = max_hp;
+            }
+            commands.entity(obj).insert(health);
+        }
+    }
+
     /// Fills walkable_tiles with terrain and fills blocked_tiles with water
-    fn spawn_terrain(&mut self, commands: &mut Commands) -> &mut GameWorld {
-        let noise = terrain_perlin(self.seed);
-        let mut rng = rand::thread_rng();
-        for x in 0..MAP_SIZE_X {
-            for y in 0..MAP_SIZE_Y {
-                let tile_pos = TilePos { x, y };
-
-                let mut perlin_value = noise.get_noise((x as f32) / 160.0, (y as f32) / 100.0);
-                perlin_value = (perlin_value + 1.0) * 0.5;
+    fn spawn_terrain(&mut self, commands: &mut Commands) -> &mut ChunkBuilder<'a> {
+        let mut rng = self.seeded_rng(1);
+        for tile_pos in chunk_tiles(self.chunk) {
+            let noise_pos = noise_pos(&tile_pos);
+            let mut perlin_value = self.world.terrain_noise.get_noise(noise_pos.x, noise_pos.y);
+            perlin_value = (perlin_value + 1.0) * 0.5;
 
-                let tile_entity = commands.spawn_empty().id();
-                let texture_index = if perlin_value > 0.05f32 && perlin_value < 0.2f32 {
-                    // Water
-                    commands.entity(tile_entity).insert(Blocking);
-                    self.blocked_tiles.push(tile_pos);
-                    TileTextureIndex(13)
+            let biome = self.biome(&tile_pos);
+            let tile_entity = commands.spawn_empty().id();
+            let (texture_index, tint) = if perlin_value > 0.05f32 && perlin_value < 0.2f32 {
+                // Water
+                commands.entity(tile_entity).insert(Blocking);
+                self.blocked_tiles.insert(tile_pos);
+                (TileTextureIndex(13), Color::WHITE)
+            } else {
+                let foilage_percent = rng.gen_range(0..100);
+                let texture_index = if foilage_percent >= biome.foliage_chance || biome.foliage.is_empty() {
+                    biome.floor
                 } else {
-                    let foilage_percent = rng.gen_range(0..100);
-                    let foilage_type = rng.gen_range(1..5);
-                    if foilage_percent >= 20 {
-                        TileTextureIndex(0)
-                    } else {
-                        TileTextureIndex(foilage_type)
-                    }
+                    biome.foliage[rng.gen_range(0..biome.foliage.len())]
                 };
-                commands.entity(tile_entity).insert(TileBundle {
-                    position: tile_pos,
-                    tilemap_id: TilemapId(self.floor_tilemap),
-                    texture_index,
-                    ..Default::default()
-                });
-
-                self.floor_tiles.set(&tile_pos, tile_entity);
-            }
+                let [r, g, b] = biome.tint;
+                (TileTextureIndex(texture_index), Color::rgb(r, g, b))
+            };
+            let (mut tile, world_pos) = world_tile(self.tilemaps.floor, &tile_pos, texture_index.0);
+            tile.color = TileColor(tint);
+            commands.entity(tile_entity).insert((tile, world_pos));
+
+            self.floor_tiles.set(&local_pos(&tile_pos), tile_entity);
         }
 
         self
     }
 
-    /// Spawns trees inside the world
-    fn spawn_trees(&mut self, commands: &mut Commands) -> &mut GameWorld {
-        let noise = tree_perlin(self.seed);
+    /// Puts back what the player built here, before anything grows so nothing ends up underneath
+    fn spawn_built(&mut self, commands: &mut Commands, item_db: &ItemDatabase) -> &mut ChunkBuilder<'a> {
+        let built_here: Vec<(TilePos, ItemId)> = self
+            .changes
+            .placed_objs
+            .iter()
+            .filter(|(pos, _)| chunk_of(pos.x, pos.y) == self.chunk)
+            .map(|(pos, id)| (*pos, *id))
+            .collect();
+        for (tile_pos, id) in built_here {
+            match item_db.items.get(&id).and_then(|item| item.placeable) {
+                Some(placeable) => {
+                    let built_entity = place_built_obj(commands, &self.tilemaps.objs, &tile_pos, id, placeable);
+                    if let Some(contents) = self.changes.containers.remove(&tile_pos) {
+                        commands
+                            .entity(built_entity)
+                            .insert(chest_inventory(&contents, item_db));
+                    }
+                    self.objs_tiles.set(&local_pos(&tile_pos), built_entity);
+                    self.blocked_tiles.insert(tile_pos);
+                }
+                None => println!(
+                    "{:?} can no longer be built, leaving {} {} empty",
+                    id, tile_pos.x, tile_pos.y
+                ),
+            }
+        }
 
-        for x in 0..MAP_SIZE_X {
-            for y in (0..MAP_SIZE_Y).step_by(2) {
+        self
+    }
+
+    /// Spawns trees inside the chunk, the tree noise decides where they could grow and the biome how many do
+    fn spawn_trees(&mut self, commands: &mut Commands) -> &mut ChunkBuilder<'a> {
+        let mut rng = self.seeded_rng(3);
+        let origin = self.chunk * CHUNK_SIZE;
+        for x in origin.x..origin.x + CHUNK_SIZE {
+            for y in (origin.y..origin.y + CHUNK_SIZE).step_by(2) {
                 let tree_base_pos = TilePos { x, y };
                 let tree_top_pos = TilePos { x, y: y + 1 };
+                // Rolled for every spot so skipping one does not shift the trees after it
+                let tree_percent = rng.gen_range(0..100);
 
-                if self.blocked_tiles.contains(&tree_base_pos) || self.blocked_tiles.contains(&tree_top_pos) {
+                if tree_percent >= self.biome(&tree_base_pos).tree_chance
+                    || self.blocked_tiles.contains(&tree_base_pos)
+                    || self.blocked_tiles.contains(&tree_top_pos)
+                    || self.changes.removed_objs.contains(&tree_base_pos)
+                {
                     continue;
                 }
 
-                let mut perlin_value = noise.get_noise((x as f32) / 160.0, (y as f32) / 100.0);
+                let noise_pos = noise_pos(&tree_base_pos);
+                let mut perlin_value = self.world.tree_noise.get_noise(noise_pos.x, noise_pos.y);
                 perlin_value = (perlin_value + 1.0) * 0.5;
 
                 if !(0.2f32..=0.6f32).contains(&perlin_value) {
                     //spawn object
-                    let (base_entity, top_entity) = place_medium_tree(commands, &self.objs_tilemap, &tree_base_pos);
-                    self.objs_tiles.set(&tree_base_pos, base_entity);
-                    self.objs_tiles.set(&tree_top_pos, top_entity);
+                    let tree = place_medium_tree(commands, &self.tilemaps.objs, &mut self.objs_tiles, &tree_base_pos);
+                    if let Some(tree) = tree {
+                        self.restore_damage(commands, tree, &tree_base_pos, TREE_HP);
+                    }
                 }
             }
         }
@@ -208,84 +615,437 @@ impl GameWorld {
         self
     }
 
-    /// Spawns flowers on tiles that do not block
-    fn spawn_flowers(&mut self, commands: &mut Commands) -> &mut GameWorld {
-        let mut rng = rand::thread_rng();
-        for x in 0..MAP_SIZE_X {
-            for y in 0..MAP_SIZE_Y {
-                let tile_pos = TilePos { x, y };
-                if self.blocked_tiles.contains(&tile_pos) {
-                    continue;
-                }
+    /// Spawns rocks on free tiles, as many as the biome asks for
+    fn spawn_rocks(&mut self, commands: &mut Commands) -> &mut ChunkBuilder<'a> {
+        let mut rng = self.seeded_rng(5);
+        for tile_pos in chunk_tiles(self.chunk) {
+            // Rolled for every tile so skipping one does not shift the rocks after it
+            let rock_percent = rng.gen_range(0..100);
+            if rock_percent >= self.biome(&tile_pos).rock_chance
+                || self.blocked_tiles.contains(&tile_pos)
+                || self.objs_tiles.get(&local_pos(&tile_pos)).is_some()
+                || self.changes.removed_objs.contains(&tile_pos)
+            {
+                continue;
+            }
 
-                let foilage_percent = rng.gen_range(0..100);
-                if foilage_percent <= 2 {
-                    commands.spawn(TileBundle {
-                        position: tile_pos,
-                        texture_index: TileTextureIndex(rng.gen_range(2..8)),
-                        tilemap_id: TilemapId(self.objs_tilemap),
-                        ..default()
-                    });
+            let rock_entity = place_rock(commands, &self.tilemaps.objs, &tile_pos);
+            self.restore_damage(commands, rock_entity, &tile_pos, ROCK_HP);
+            self.objs_tiles.set(&local_pos(&tile_pos), rock_entity);
+            self.blocked_tiles.insert(tile_pos);
+        }
+
+        self
+    }
+
+    /// Spawns dirt mounds, they can be walked over but only a shovel digs them up
+    fn spawn_mounds(&mut self, commands: &mut Commands) -> &mut ChunkBuilder<'a> {
+        let mut rng = self.seeded_rng(6);
+        for tile_pos in chunk_tiles(self.chunk) {
+            // Rolled for every tile so skipping one does not shift the mounds after it
+            let mound_percent = rng.gen_range(0..100);
+            if mound_percent >= self.biome(&tile_pos).mound_chance
+                || self.blocked_tiles.contains(&tile_pos)
+                || self.objs_tiles.get(&local_pos(&tile_pos)).is_some()
+                || self.changes.removed_objs.contains(&tile_pos)
+            {
+                continue;
+            }
+
+            let mound_entity = place_mound(commands, &self.tilemaps.objs, &tile_pos);
+            self.restore_damage(commands, mound_entity, &tile_pos, MOUND_HP);
+            self.objs_tiles.set(&local_pos(&tile_pos), mound_entity);
+        }
+
+        self
+    }
+
+    /// Spawns flowers on tiles that do not block and are not taken by another object
+    fn spawn_flowers(&mut self, commands: &mut Commands) -> &mut ChunkBuilder<'a> {
+        let mut rng = self.seeded_rng(2);
+        for tile_pos in chunk_tiles(self.chunk) {
+            // Rolled for every tile so skipping one does not shift the flowers after it
+            let foilage_percent = rng.gen_range(0..100);
+            let flowers = &self.biome(&tile_pos).flowers;
+            if foilage_percent >= self.biome(&tile_pos).flower_chance
+                || flowers.is_empty()
+                || self.blocked_tiles.contains(&tile_pos)
+                || self.objs_tiles.get(&local_pos(&tile_pos)).is_some()
+                || self.changes.removed_objs.contains(&tile_pos)
+            {
+                continue;
+            }
+
+            let flower_type = flowers[rng.gen_range(0..flowers.len())];
+            let kind = match flower_type {
+                MUSHROOM_RED_SMALL => ObjectKind::Mushroom,
+                _ => ObjectKind::Flower,
+            };
+            // Picked with a single hit, but they do not stand in the way
+            let flower_entity = commands
+                .spawn((
+                    world_tile(self.tilemaps.objs, &tile_pos, flower_type),
+                    Health::new(1),
+                    Interact::Harvest,
+                    kind,
+                ))
+                .id();
+            self.objs_tiles.set(&local_pos(&tile_pos), flower_entity);
+        }
+
+        self
+    }
+
+    /// Picks free tiles for the biome's items, only the first time the chunk is generated
+    /// since after that any items left are kept in `WorldChanges`
+    fn spawn_items(&mut self, items: &mut Vec<(TilePos, ItemId)>) -> &mut ChunkBuilder<'a> {
+        if self.changes.visited_chunks.contains(&self.chunk) {
+            return self;
+        }
+
+        let mut rng = self.seeded_rng(4);
+        for tile_pos in chunk_tiles(self.chunk) {
+            let mut roll = rng.gen_range(0f32..100f32);
+            if self.blocked_tiles.contains(&tile_pos) || self.objs_tiles.get(&local_pos(&tile_pos)).is_some() {
+                continue;
+            }
+
+            for item in self.biome(&tile_pos).items.iter() {
+                if roll < item.chance {
+                    items.push((tile_pos, ItemId(item.id)));
+                    break;
                 }
+                roll -= item.chance;
             }
         }
 
         self
     }
+
+    /// Lays the biome's items down along with the ones left here when the chunk unloaded
+    fn spawn_ground_items(
+        &mut self,
+        commands: &mut Commands,
+        item_db: &ItemDatabase,
+        biome_items: &[(TilePos, ItemId)],
+    ) -> &mut ChunkBuilder<'a> {
+        let chunk = self.chunk;
+        let dropped_here: Vec<TilePos> = self
+            .changes
+            .ground_items
+            .keys()
+            .filter(|pos| chunk_of(pos.x, pos.y) == chunk)
+            .copied()
+            .collect();
+        let mut ground_items: Vec<(TilePos, ItemId, ItemQuantity)> = biome_items
+            .iter()
+            .map(|(pos, id)| (*pos, *id, ItemQuantity(1)))
+            .collect();
+        for pos in dropped_here {
+            if let Some((id, qty)) = self.changes.ground_items.remove(&pos) {
+                ground_items.push((pos, id, qty));
+            }
+        }
+
+        for (tile_pos, id, qty) in ground_items {
+            if let Some(item) = item_db.items.get(&id) {
+                let item_entity = commands
+                    .spawn((
+                        world_tile(self.tilemaps.items, &tile_pos, item.atlas_index),
+                        item.clone(),
+                        qty,
+                    ))
+                    .id();
+                self.item_tiles.set(&local_pos(&tile_pos), item_entity);
+            }
+        }
+
+        self
+    }
+
+    /// Turns the storages into the chunk's tilemaps, the tiles become their children
+    fn finish(self, commands: &mut Commands) -> ChunkTilemaps {
+        let world = self.world;
+        for (tilemap, storage) in [
+            (self.tilemaps.floor, &self.floor_tiles),
+            (self.tilemaps.objs, &self.objs_tiles),
+            (self.tilemaps.items, &self.item_tiles),
+        ] {
+            let tiles: Vec<Entity> = storage.iter().flatten().copied().collect();
+            commands.entity(tilemap).push_children(&tiles);
+        }
+        commands.entity(self.tilemaps.floor).insert((
+            chunk_tilemap(world.terrain.clone(), self.chunk, FLOOR_Z, self.floor_tiles),
+            GroundStorage,
+        ));
+        commands.entity(self.tilemaps.objs).insert((
+            chunk_tilemap(world.world_objs.clone(), self.chunk, OBJECT_Z, self.objs_tiles),
+            ObjectStorage,
+        ));
+        commands.entity(self.tilemaps.items).insert((
+            chunk_tilemap(world.items.clone(), self.chunk, ITEM_Z, self.item_tiles),
+            ItemStorage,
+        ));
+        self.tilemaps
+    }
 }
 
-fn place_medium_tree(commands: &mut Commands, blocked_tilemap: &Entity, tree_base_pos: &TilePos) -> (Entity, Entity) {
-    let base_entity = commands.spawn_empty().id();
-    let top_entity = commands.spawn_empty().id();
-    let obj_size = ObjectSize::Multi(base_entity);
+impl GameWorld {
+    pub fn biome_at(&self, tile_pos: &TilePos) -> &BiomeSettings {
+        &self.biomes[self.biome_index(tile_pos)]
+    }
 
-    commands.entity(base_entity).insert((
-        TileBundle {
-            position: *tree_base_pos,
-            tilemap_id: TilemapId(*blocked_tilemap),
-            texture_index: TileTextureIndex(TREE_BASE),
-            ..default()
-        },
+    /// Picks the first biome whose ranges contain the temperature and moisture at the tile
+    fn biome_index(&self, tile_pos: &TilePos) -> usize {
+        let noise_pos = noise_pos(tile_pos);
+        let temperature = (self.temperature_noise.get_noise(noise_pos.x, noise_pos.y) + 1.0) * 0.5;
+        let moisture = (self.moisture_noise.get_noise(noise_pos.x, noise_pos.y) + 1.0) * 0.5;
+        self.biomes
+            .iter()
+            .position(|biome| biome.contains(temperature, moisture))
+            .unwrap_or(self.biomes.len() - 1)
+    }
+
+    /// The tilemap the tile is in, None while its chunk isn't generated
+    pub fn tilemap_at(&self, layer: Layer, tile_pos: &TilePos) -> Option<Entity> {
+        let chunk = self.chunks.get(&chunk_of(tile_pos.x, tile_pos.y))?;
+        Some(chunk.get(layer))
+    }
+}
+
+/// Finds what is on a tile straight from the storage of its chunk, no going over every tile in the world
+/// Everything that spawns a tile sets it in its storage and everything that despawns one removes it
+#[derive(SystemParam)]
+#[allow(clippy::type_complexity)]
+pub struct WorldTiles<'w, 's> {
+    world: Option<Res<'w, GameWorld>>,
+    storages: Query<'w, 's, &'static TileStorage>,
+    // Builds are only in the way when they block, natural objs always are
+    in_the_way_q: Query<'w, 's, (), Or<(With<Blocking>, (With<ObjectSize>, Without<Built>))>>,
+}
+
+impl<'w, 's> WorldTiles<'w, 's> {
+    pub fn ground_at(&self, tile_pos: &TilePos) -> Option<Entity> {
+        self.tile_at(Layer::Floor, tile_pos)
+    }
+
+    pub fn obj_at(&self, tile_pos: &TilePos) -> Option<Entity> {
+        self.tile_at(Layer::Objs, tile_pos)
+    }
+
+    pub fn item_at(&self, tile_pos: &TilePos) -> Option<Entity> {
+        self.tile_at(Layer::Items, tile_pos)
+    }
+
+    /// Generated ground that isn't water, with nothing standing on it that is in the way
+    /// Flowers, floors and such can be walked over
+    pub fn walkable(&self, tile_pos: &TilePos) -> bool {
+        let ground = self.ground_at(tile_pos);
+        let obj = self.obj_at(tile_pos);
+        ground.is_some_and(|ground| !self.in_the_way_q.contains(ground))
+            && !obj.is_some_and(|obj| self.in_the_way_q.contains(obj))
+    }
+
+    // Chunks that aren't generated are simply empty
+    fn tile_at(&self, layer: Layer, tile_pos: &TilePos) -> Option<Entity> {
+        let tilemap = self.world.as_ref()?.tilemap_at(layer, tile_pos)?;
+        self.storages.get(tilemap).ok()?.get(&local_pos(tile_pos))
+    }
+}
+
+/// Puts tiles into and takes them out of the storage of their chunk, for anything that spawns
+/// or despawns tiles after the chunk was generated. New tiles also have to become children
+/// of the chunk's tilemap so they unload along with it
+#[derive(SystemParam)]
+pub struct WorldTilesMut<'w, 's> {
+    world: Option<Res<'w, GameWorld>>,
+    storages: Query<'w, 's, &'static mut TileStorage>,
+}
+
+impl<'w, 's> WorldTilesMut<'w, 's> {
+    pub fn get(&self, layer: Layer, tile_pos: &TilePos) -> Option<Entity> {
+        let tilemap = self.tilemap(layer, tile_pos)?;
+        self.storages.get(tilemap).ok()?.get(&local_pos(tile_pos))
+    }
+
+    /// The tilemap a new tile on the position goes into, None while its chunk isn't generated
+    pub fn tilemap(&self, layer: Layer, tile_pos: &TilePos) -> Option<Entity> {
+        self.world.as_ref()?.tilemap_at(layer, tile_pos)
+    }
+
+    pub fn set(&mut self, layer: Layer, tile_pos: &TilePos, tile_entity: Entity) {
+        if let Some(mut storage) = self.storage_mut(layer, tile_pos) {
+            storage.set(&local_pos(tile_pos), tile_entity);
+        }
+    }
+
+    pub fn remove(&mut self, layer: Layer, tile_pos: &TilePos) {
+        if let Some(mut storage) = self.storage_mut(layer, tile_pos) {
+            storage.remove(&local_pos(tile_pos));
+        }
+    }
+
+    fn storage_mut(&mut self, layer: Layer, tile_pos: &TilePos) -> Option<Mut<'_, TileStorage>> {
+        let tilemap = self.tilemap(layer, tile_pos)?;
+        self.storages.get_mut(tilemap).ok()
+    }
+}
+
+/// Despawns the chunk's tilemaps, every tile is a child of its tilemap and goes with it
+fn despawn_chunk(commands: &mut Commands, tilemaps: &ChunkTilemaps) {
+    for tilemap in [tilemaps.floor, tilemaps.objs, tilemaps.items] {
+        commands.entity(tilemap).despawn_recursive();
+    }
+}
+
+/// Spawns an obj that covers more than one tile, `parts` are the offset from the base tile and the sprite of
+/// every tile. The first part is the owner which gets the obj's data, the rest only point back at it
+/// Nothing is spawned when a part would end up in another chunk, they load and unload on their own,
+/// or on a tile that already has an obj. `objs_tiles` is the storage of the base tile's chunk
+pub fn place_multi_obj(
+    commands: &mut Commands,
+    blocked_tilemap: &Entity,
+    objs_tiles: &mut TileStorage,
+    base_pos: &TilePos,
+    parts: &[(UVec2, u32)],
+) -> Option<Entity> {
+    let mut footprint = Vec::with_capacity(parts.len());
+    for (offset, _) in parts {
+        let tile_pos = TilePos {
+            x: base_pos.x.checked_add(offset.x)?,
+            y: base_pos.y.checked_add(offset.y)?,
+        };
+        if chunk_of(tile_pos.x, tile_pos.y) != chunk_of(base_pos.x, base_pos.y)
+            || objs_tiles.get(&local_pos(&tile_pos)).is_some()
+        {
+            return None;
+        }
+        footprint.push((tile_pos, commands.spawn_empty().id()));
+    }
+
+    let (_, owner) = *footprint.first()?;
+    for ((tile_pos, tile_entity), (_, sprite)) in footprint.iter().zip(parts) {
+        commands.entity(*tile_entity).insert((
+            world_tile(*blocked_tilemap, tile_pos, *sprite),
+            ObjectSize::Multi(owner),
+        ));
+        objs_tiles.set(&local_pos(tile_pos), *tile_entity);
+    }
+    commands.entity(owner).insert(Footprint(footprint));
+
+    Some(owner)
+}
+
+/// Despawns a world obj along with every tile it covers and takes them out of the objs storage
+pub fn despawn_obj(
+    commands: &mut Commands,
+    world_tiles: &mut WorldTilesMut,
+    obj: Entity,
+    obj_pos: &TilePos,
+    footprint: Option<&Footprint>,
+) {
+    let single = [(*obj_pos, obj)];
+    let tiles = footprint.map_or(&single[..], |footprint| &footprint.0[..]);
+    for (tile_pos, tile_entity) in tiles {
+        // Something else may have been put on the tile since
+        if world_tiles.get(Layer::Objs, tile_pos) == Some(*tile_entity) {
+            world_tiles.remove(Layer::Objs, tile_pos);
+        }
+        if let Some(tile_commands) = commands.get_entity(*tile_entity) {
+            tile_commands.despawn_recursive();
+        }
+    }
+}
+
+fn place_medium_tree(
+    commands: &mut Commands,
+    blocked_tilemap: &Entity,
+    objs_tiles: &mut TileStorage,
+    tree_base_pos: &TilePos,
+) -> Option<Entity> {
+    let tree = place_multi_obj(
+        commands,
+        blocked_tilemap,
+        objs_tiles,
+        tree_base_pos,
+        &[(UVec2::ZERO, TREE_BASE), (UVec2::Y, TREE_TOP)],
+    )?;
+    commands.entity(tree).insert((
         Tree,
-        Health::new(5),
+        Health::new(TREE_HP),
         Interact::Harvest,
+        ToolRequirement {
+            kind: ToolKind::Hatchet,
+            strict: false,
+        },
+        ObjectKind::Tree,
         Blocking,
-        obj_size,
     ));
-    commands.entity(top_entity).insert((
-        TileBundle {
-            position: TilePos {
-                x: tree_base_pos.x,
-                y: tree_base_pos.y + 1,
+
+    Some(tree)
+}
+
+fn place_rock(commands: &mut Commands, blocked_tilemap: &Entity, rock_pos: &TilePos) -> Entity {
+    commands
+        .spawn((
+            world_tile(*blocked_tilemap, rock_pos, ROCK),
+            Health::new(ROCK_HP),
+            Interact::Harvest,
+            // Too hard to break by hand
+            ToolRequirement {
+                kind: ToolKind::Pickaxe,
+                strict: true,
             },
-            tilemap_id: TilemapId(*blocked_tilemap),
-            texture_index: TileTextureIndex(TREE_TOP),
-            ..default()
-        },
-        obj_size,
-    ));
+            ObjectKind::Rock,
+            Blocking,
+            ObjectSize::Single,
+        ))
+        .id()
+}
 
-    (base_entity, top_entity)
-}
-
-// Creates entity to hold all item entities when they exist in the overworld
-fn create_item_tilestorage(mut commands: Commands, tiles: Res<SpriteAssets>) {
-    let item_tilemap = commands.spawn_empty().id();
-    let tilemap_size = world_size();
-    let item_tiles = TileStorage::empty(tilemap_size);
-    commands.entity(item_tilemap).insert((
-        TilemapBundle {
-            grid_size: tilegridsize_pixels(),
-            map_type: TilemapType::Square,
-            size: tilemap_size,
-            storage: item_tiles,
-            texture: TilemapTexture::Single(tiles.items.clone()),
-            tile_size: tilemaptilesize_pixels(),
-            transform: Transform::from_translation(Vec3::new(0f32, 0f32, ITEM_Z)),
-            ..Default::default()
-        },
-        ItemStorage,
-    ));
+fn place_mound(commands: &mut Commands, blocked_tilemap: &Entity, mound_pos: &TilePos) -> Entity {
+    commands
+        .spawn((
+            world_tile(*blocked_tilemap, mound_pos, DIRT_MOUND),
+            Health::new(MOUND_HP),
+            Interact::Harvest,
+            // Hands and the other tools just scatter the dirt around
+            ToolRequirement {
+                kind: ToolKind::Shovel,
+                strict: true,
+            },
+            ObjectKind::Mound,
+        ))
+        .id()
+}
+
+/// Spawns a world obj the player built out of a placeable item
+pub fn place_built_obj(
+    commands: &mut Commands,
+    blocked_tilemap: &Entity,
+    pos: &TilePos,
+    id: ItemId,
+    placeable: Placeable,
+) -> Entity {
+    let built_entity = commands
+        .spawn((
+            world_tile(*blocked_tilemap, pos, placeable.sprite),
+            Built(id),
+            ObjectSize::Single,
+        ))
+        .id();
+    if placeable.blocking {
+        commands.entity(built_entity).insert(Blocking);
+    }
+    if let Some(station) = placeable.station {
+        commands.entity(built_entity).insert(station);
+    }
+    if placeable.container {
+        commands
+            .entity(built_entity)
+            .insert((Chest, Inventory::with_slots(CHEST_SLOTS)));
+    }
+    built_entity
 }
 
 fn stretch_tree(mut tree_q: Query<(&mut Transform, &TilePos), With<Tree>>, keeb: Res<Input<KeyCode>>) {
@@ -300,6 +1060,100 @@ fn stretch_tree(mut tree_q: Query<(&mut Transform, &TilePos), With<Tree>>, keeb:
     }
 }
 
+//=====> Seed prompt
+// While it exists the game is paused and typed digits are collected into the new seed
+#[derive(Resource)]
+struct SeedPrompt {
+    input: String,
+}
+
+#[derive(Component)]
+struct SeedPromptUi;
+
+fn create_seed_prompt_ui(mut commands: Commands, font: Res<FontAssets>) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: font.chunk.clone(),
+                font_size: 30.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(10.),
+                top: Val::Px(10.),
+                ..default()
+            },
+            ..default()
+        }),
+        SeedPromptUi,
+    ));
+}
+
+// P opens the prompt to type in a seed for a new world
+fn open_seed_prompt(
+    mut commands: Commands,
+    mut prompt_ui_q: Query<&mut Text, With<SeedPromptUi>>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    if !keeb.just_pressed(KeyCode::P) {
+        return;
+    }
+
+    if let Ok(mut text) = prompt_ui_q.get_single_mut() {
+        text.sections[0].value = "Seed: ".to_string();
+    }
+    commands.insert_resource(SeedPrompt { input: String::new() });
+    commands.insert_resource(NextState(GameState::Menu));
+}
+
+// Enter regenerates the world with the typed seed, P closes the prompt without changing anything
+fn seed_prompt_input(
+    mut commands: Commands,
+    mut prompt: ResMut<SeedPrompt>,
+    mut seed: ResMut<WorldSeed>,
+    mut prompt_ui_q: Query<&mut Text, With<SeedPromptUi>>,
+    mut ev_chars: EventReader<ReceivedCharacter>,
+    mut ev_regenerate: EventWriter<RegenerateWorldEvent>,
+    keeb: Res<Input<KeyCode>>,
+) {
+    for ev in ev_chars.iter() {
+        if ev.char.is_ascii_digit() {
+            prompt.input.push(ev.char);
+        }
+    }
+    if keeb.just_pressed(KeyCode::Back) {
+        prompt.input.pop();
+    }
+
+    let mut close = keeb.just_pressed(KeyCode::P);
+    if keeb.just_pressed(KeyCode::Return) {
+        match prompt.input.parse::<u64>() {
+            Ok(new_seed) => {
+                seed.0 = new_seed;
+                ev_regenerate.send(RegenerateWorldEvent);
+                close = true;
+            }
+            Err(_) => println!("{} is not a valid seed", prompt.input),
+        }
+    }
+
+    if let Ok(mut text) = prompt_ui_q.get_single_mut() {
+        text.sections[0].value = if close {
+            String::new()
+        } else {
+            format!("Seed: {}", prompt.input)
+        };
+    }
+    if close {
+        commands.remove_resource::<SeedPrompt>();
+        commands.insert_resource(NextState(GameState::Running));
+    }
+}
+
 // Perlin example
 // let mut perlin_value = noise.get_noise((x as f32) / 160.0, (y as f32) / 100.0);
 // perlin_value = (perlin_value + 1.0) * 0.5;
@@ -313,21 +1167,113 @@ fn stretch_tree(mut tree_q: Query<(&mut Transform, &TilePos), With<Tree>>, keeb:
 //       _ => 0,
 //     };
 
-/// Returns the tile postion in the world with respect to tile size
-#[allow(dead_code)]
-fn tile_pos_to_world_pos(tile_pos: TilePos) -> Vec2 {
-    tile_pos.center_in_world(&tilegridsize_pixels(), &TilemapType::Square)
-        + Vec2::new(TILE_PIXELS_X / 2f32, TILE_PIXELS_Y / 2f32)
+/// Where a tile's center is drawn, counted from the world origin
+pub fn tile_to_world(x: u32, y: u32) -> Vec2 {
+    origin_offset(x, y) * Vec2::new(TILE_PIXELS_X, TILE_PIXELS_Y)
 }
 
-pub fn within_bounds(tile: Vec2) -> bool {
-    tile.x >= 0.0 && tile.x < MAP_SIZE_X as f32 && tile.y >= 0.0 && tile.y < MAP_SIZE_Y as f32
+/// The tile under a point in the world, None past the last tile
+pub fn world_to_tile(world_pos: Vec2) -> Option<TilePos> {
+    // Tiles are centered on their position
+    let tile = (world_pos / Vec2::new(TILE_PIXELS_X, TILE_PIXELS_Y)).round();
+    Some(TilePos {
+        x: u32::try_from(tile.x as i64 + WORLD_ORIGIN as i64).ok()?,
+        y: u32::try_from(tile.y as i64 + WORLD_ORIGIN as i64).ok()?,
+    })
 }
 
-pub fn world_size() -> TilemapSize {
+fn origin_offset(x: u32, y: u32) -> Vec2 {
+    Vec2::new(
+        (x as i64 - WORLD_ORIGIN as i64) as f32,
+        (y as i64 - WORLD_ORIGIN as i64) as f32,
+    )
+}
+
+// Where the noises get sampled for a tile
+fn noise_pos(tile_pos: &TilePos) -> Vec2 {
+    origin_offset(tile_pos.x, tile_pos.y) / Vec2::new(160.0, 100.0)
+}
+
+/// The tile that many tiles over, None past the last tile
+pub fn offset_tile(tile_pos: &TilePos, dx: i32, dy: i32) -> Option<TilePos> {
+    Some(TilePos {
+        x: tile_pos.x.checked_add_signed(dx)?,
+        y: tile_pos.y.checked_add_signed(dy)?,
+    })
+}
+
+/// The chunk a tile belongs to
+pub fn chunk_of(x: u32, y: u32) -> UVec2 {
+    UVec2::new(x / CHUNK_SIZE, y / CHUNK_SIZE)
+}
+
+/// Where the tile sits inside of its chunk, which is what the chunk's tilemaps and storages go by
+pub fn local_pos(tile_pos: &TilePos) -> TilePos {
+    TilePos {
+        x: tile_pos.x % CHUNK_SIZE,
+        y: tile_pos.y % CHUNK_SIZE,
+    }
+}
+
+/// A tile of a chunk's tilemap, put where it is inside of the chunk and keeping where it is in the world
+pub fn world_tile(tilemap: Entity, tile_pos: &TilePos, texture: u32) -> (TileBundle, WorldTilePos) {
+    (
+        TileBundle {
+            position: local_pos(tile_pos),
+            tilemap_id: TilemapId(tilemap),
+            texture_index: TileTextureIndex(texture),
+            ..default()
+        },
+        WorldTilePos(*tile_pos),
+    )
+}
+
+/// Tiles around the origin out to the radius, closest first with the straight neighbours before the diagonal ones
+pub fn tiles_around(origin: TilePos, radius: i32) -> impl Iterator<Item = TilePos> {
+    let mut offsets: Vec<(i32, i32)> = Vec::new();
+    for ring in 0..=radius {
+        let mut ring_offsets: Vec<(i32, i32)> = (-ring..=ring)
+            .flat_map(|dx| (-ring..=ring).map(move |dy| (dx, dy)))
+            .filter(|(dx, dy)| dx.abs().max(dy.abs()) == ring)
+            .collect();
+        ring_offsets.sort_by_key(|(dx, dy)| dx.abs() + dy.abs());
+        offsets.extend(ring_offsets);
+    }
+
+    offsets
+        .into_iter()
+        .filter_map(move |(dx, dy)| offset_tile(&origin, dx, dy))
+}
+
+/// The tiles above, below and to either side, the ones the player can step onto
+pub fn straight_neighbours(tile_pos: TilePos) -> impl Iterator<Item = TilePos> {
+    tiles_around(tile_pos, 1)
+        .filter(move |neighbour| neighbour.x == tile_pos.x || neighbour.y == tile_pos.y)
+        .skip(1)
+}
+
+/// Every tile position inside the chunk
+pub fn chunk_tiles(chunk: UVec2) -> impl Iterator<Item = TilePos> {
+    let origin = chunk * CHUNK_SIZE;
+    (origin.x..origin.x + CHUNK_SIZE)
+        .flat_map(move |x| (origin.y..origin.y + CHUNK_SIZE).map(move |y| TilePos { x, y }))
+}
+
+/// The chunks in a square around the center chunk
+pub fn chunks_around(center: UVec2, radius: u32) -> impl Iterator<Item = UVec2> {
+    let min = UVec2::new(center.x.saturating_sub(radius), center.y.saturating_sub(radius));
+    let max = UVec2::new(center.x.saturating_add(radius), center.y.saturating_add(radius));
+    (min.x..=max.x).flat_map(move |x| (min.y..=max.y).map(move |y| UVec2::new(x, y)))
+}
+
+fn chunk_distance(a: UVec2, b: UVec2) -> u32 {
+    a.x.abs_diff(b.x).max(a.y.abs_diff(b.y))
+}
+
+pub fn chunk_size() -> TilemapSize {
     TilemapSize {
-        x: MAP_SIZE_X,
-        y: MAP_SIZE_Y,
+        x: CHUNK_SIZE,
+        y: CHUNK_SIZE,
     }
 }
 
@@ -348,12 +1294,19 @@ pub fn tilemaptilesize_pixels() -> TilemapTileSize {
 //====> World Data Components
 // Marks a tile as being part of an object, the Entity will contain the data for the object
 #[derive(Clone, Copy, Component)]
-#[allow(dead_code)]
 pub enum ObjectSize {
     Single,
     Multi(Entity),
 }
 
+// Every tile of a multi tile object and the entity on it, the owner keeps this so they all go together
+#[derive(Component)]
+pub struct Footprint(pub Vec<(TilePos, Entity)>);
+
+// Where a tile is in the world, its TilePos is only where it is inside of its chunk's tilemap
+#[derive(Clone, Copy, Component)]
+pub struct WorldTilePos(pub TilePos);
+
 // Describes the tile storage's main purpose
 #[derive(Component)]
 pub struct GroundStorage;
@@ -371,26 +1324,7 @@ pub struct Blocking;
 struct Tree;
 
 //=====> Perlin generators and settings
-fn terrain_perlin(seed: u64) -> FastNoise {
-    let config = match load_settings("terrainperlin") {
-        Ok(config) => config,
-        Err(_) => panic!("Could not load terrainperlin settings"),
-    };
-    let mut noise = FastNoise::seeded(seed);
-    noise.set_noise_type(NoiseType::SimplexFractal);
-    noise.set_fractal_type(FractalType::FBM);
-    noise.set_fractal_octaves(config.octaves);
-    noise.set_fractal_gain(config.gain);
-    noise.set_fractal_lacunarity(config.lacunarity);
-    noise.set_frequency(config.frequency);
-    noise
-}
-
-fn tree_perlin(seed: u64) -> FastNoise {
-    let config = match load_settings("treeperlin") {
-        Ok(config) => config,
-        Err(_) => panic!("Could not load treeperlin settings"),
-    };
+fn fractal_perlin(config: &FractalSettings, seed: u64) -> FastNoise {
     let mut noise = FastNoise::seeded(seed);
     noise.set_noise_type(NoiseType::SimplexFractal);
     noise.set_fractal_type(FractalType::FBM);
@@ -0,0 +1,339 @@
+/// World Generation CLI
+///
+/// `comfort_rpg --generate <name> [--seed <number>] [--radius <chunks>] [--config <file>] [--bench-lookups]`
+/// Runs the world generation around the spawn point without opening a window and writes
+/// <name>.png, a preview drawn with the game's sprites, and <name>.json, a dump of every tile
+/// Handy for comparing seeds and noise settings or keeping golden files of generated worlds
+/// `--bench-lookups` also benchmarks the tile lookups the game does on every step, on a world of at least
+/// BENCH_RADIUS chunks around the spawn
+use bevy::{ecs::system::SystemState, prelude::*};
+use bevy_ecs_tilemap::prelude::*;
+use image::{imageops, RgbaImage};
+use serde::Serialize;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, Instant},
+};
+
+use crate::{
+    comfort_config::load_config,
+    item_util::{load_items_from_json, ItemId},
+    world_gen::{
+        chunk_of, chunk_tiles, chunks_around, generate_headless, GameWorld, GroundStorage, ObjectStorage, WorldSeed,
+        WorldTilePos, WorldTiles, CHUNK_SIZE, TILE_PIXELS_X, TILE_PIXELS_Y, WORLD_ORIGIN,
+    },
+};
+
+const DEFAULT_RADIUS: u32 = 2; // The same area the game generates around a new player
+const BENCH_RADIUS: u32 = 6; // 13 by 13 chunks, the bigger the world the slower scanning every tile gets
+const BENCH_SAMPLES: usize = 256; // Tiles looked up per run, spread evenly over the world
+const BENCH_WARMUP_RUNS: usize = 2; // Not timed, they fill the caches and let the query states settle
+const BENCH_RUNS: usize = 10;
+
+pub struct GenerateOptions {
+    out: PathBuf,
+    radius: u32,
+    bench_lookups: bool,
+}
+
+#[derive(Serialize)]
+struct TileDump {
+    seed: u64,
+    // Tile position of the first entry of every layer
+    origin: [u32; 2],
+    width: u32,
+    height: u32,
+    // Layers are indexed [y][x] from the origin, so rows go up just like tile positions
+    // Terrain atlas index of every tile
+    floor: Vec<Vec<u32>>,
+    // World objs atlas index, null where nothing stands on the floor
+    objects: Vec<Vec<Option<u32>>>,
+    biomes: Vec<Vec<String>>,
+    items: Vec<DumpedItem>,
+    // Only needed to draw the preview
+    #[serde(skip)]
+    tints: Vec<Vec<[f32; 4]>>,
+}
+
+#[derive(Serialize)]
+struct DumpedItem {
+    x: u32,
+    y: u32,
+    id: u32,
+}
+
+/// Reads `--generate <name>` and the options that go with it, None when the game should start like normal
+pub fn options_from_args() -> Option<GenerateOptions> {
+    let args: Vec<String> = env::args().collect();
+    let out = args
+        .iter()
+        .position(|arg| arg == "--generate")
+        .and_then(|idx| args.get(idx + 1))?;
+
+    let radius = match args
+        .iter()
+        .position(|arg| arg == "--radius")
+        .and_then(|idx| args.get(idx + 1))
+    {
+        Some(radius) => match radius.parse::<u32>() {
+            Ok(radius) => radius,
+            Err(_) => {
+                println!("--radius expects a positive number, using {DEFAULT_RADIUS}");
+                DEFAULT_RADIUS
+            }
+        },
+        None => DEFAULT_RADIUS,
+    };
+
+    Some(GenerateOptions {
+        out: PathBuf::from(out),
+        radius,
+        bench_lookups: args.iter().any(|arg| arg == "--bench-lookups"),
+    })
+}
+
+/// Generates the world and writes both files, exits with an error code when either could not be written
+pub fn run(options: GenerateOptions) {
+    let seed = WorldSeed::from_env().0;
+    // Unlike the game a broken config stops here, a preview of the built-in settings would only be misleading
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            println!("{err}");
+            process::exit(1);
+        }
+    };
+    // Players start at the world origin
+    let center = chunk_of(WORLD_ORIGIN, WORLD_ORIGIN);
+    let chunks: Vec<UVec2> = chunks_around(center, options.radius).collect();
+
+    let mut ecs_world = World::new();
+    let items = generate_headless(&mut ecs_world, seed, config, &chunks);
+    if options.bench_lookups {
+        bench_lookups(seed, options.radius.max(BENCH_RADIUS));
+    }
+    let dump = dump_tiles(&mut ecs_world, seed, &chunks, items);
+
+    let png_path = options.out.with_extension("png");
+    let json_path = options.out.with_extension("json");
+    let results = [
+        (&png_path, write_preview(&png_path, &dump)),
+        (&json_path, write_dump(&json_path, &dump)),
+    ];
+
+    let mut failed = false;
+    for (path, result) in results {
+        match result {
+            Ok(_) => println!("Wrote {}", path.display()),
+            Err(err) => {
+                println!("Could not write {}, {err}", path.display());
+                failed = true;
+            }
+        }
+    }
+    if failed {
+        process::exit(1);
+    }
+}
+
+// Finds what is on a spread of tiles twice, once going over every tile entity for each of them like the game
+// used to and once through the tilemaps' storages. Each way is warmed up and then timed over several runs,
+// the median runs are compared. The bench gets a world of its own so the preview keeps the asked for radius
+fn bench_lookups(seed: u64, radius: u32) {
+    let config = match load_config() {
+        Ok(config) => config,
+        Err(err) => {
+            println!("Skipping the lookup bench, {err}");
+            return;
+        }
+    };
+    let center = chunk_of(WORLD_ORIGIN, WORLD_ORIGIN);
+    let chunks: Vec<UVec2> = chunks_around(center, radius).collect();
+    let mut ecs_world = World::new();
+    generate_headless(&mut ecs_world, seed, config, &chunks);
+
+    let tiles: Vec<TilePos> = chunks.iter().flat_map(|chunk| chunk_tiles(*chunk)).collect();
+    let samples: Vec<TilePos> = tiles
+        .iter()
+        .step_by((tiles.len() / BENCH_SAMPLES).max(1))
+        .copied()
+        .collect();
+
+    let mut tiles_q = ecs_world.query::<&WorldTilePos>();
+    let tile_entities = tiles_q.iter(&ecs_world).count();
+    let (scanned, scan_time) = time_runs(|| {
+        samples
+            .iter()
+            .map(|sample| tiles_q.iter(&ecs_world).filter(|pos| pos.0 == *sample).count())
+            .sum()
+    });
+
+    let mut tiles_state: SystemState<WorldTiles> = SystemState::new(&mut ecs_world);
+    let world_tiles = tiles_state.get(&ecs_world);
+    let (looked_up, lookup_time) = time_runs(|| {
+        samples
+            .iter()
+            .map(|sample| {
+                [
+                    world_tiles.ground_at(sample),
+                    world_tiles.obj_at(sample),
+                    world_tiles.item_at(sample),
+                ]
+                .into_iter()
+                .flatten()
+                .count()
+            })
+            .sum()
+    });
+
+    let side = radius * 2 + 1;
+    println!(
+        "Looked up {} tiles on {side}x{side} chunks with {tile_entities} tile entities, median of {BENCH_RUNS} runs",
+        samples.len()
+    );
+    println!("  scanning every tile: {scan_time:?} per run, found {scanned}");
+    println!("  tilemap storages:    {lookup_time:?} per run, found {looked_up}");
+    println!(
+        "  the storages are {:.0}x faster",
+        scan_time.as_secs_f64() / lookup_time.as_secs_f64().max(f64::EPSILON)
+    );
+    if scanned != looked_up {
+        println!("  both ways should find the same tiles, something is out of sync");
+    }
+}
+
+// Runs the lookups BENCH_WARMUP_RUNS times untimed and then BENCH_RUNS times timed,
+// gives back what the last run found and how long the median run took
+fn time_runs(mut lookups: impl FnMut() -> usize) -> (usize, Duration) {
+    for _ in 0..BENCH_WARMUP_RUNS {
+        lookups();
+    }
+    let mut found = 0;
+    let mut times: Vec<Duration> = (0..BENCH_RUNS)
+        .map(|_| {
+            let start = Instant::now();
+            found = lookups();
+            start.elapsed()
+        })
+        .collect();
+    times.sort();
+    (found, times[BENCH_RUNS / 2])
+}
+
+// Reads the generated tiles back out of the ECS world into plain layers
+fn dump_tiles(ecs_world: &mut World, seed: u64, chunks: &[UVec2], items: Vec<(TilePos, ItemId)>) -> TileDump {
+    let min = chunks.iter().fold(UVec2::splat(u32::MAX), |min, chunk| min.min(*chunk)) * CHUNK_SIZE;
+    let max = (chunks.iter().fold(UVec2::ZERO, |max, chunk| max.max(*chunk)) + 1) * CHUNK_SIZE;
+    let (width, height) = (max.x - min.x, max.y - min.y);
+
+    let mut floor = vec![vec![0; width as usize]; height as usize];
+    let mut objects = vec![vec![None; width as usize]; height as usize];
+    let mut tints = vec![vec![[1.0; 4]; width as usize]; height as usize];
+    let mut tiles_q = ecs_world.query::<(&WorldTilePos, &TileTextureIndex, &TileColor, &TilemapId)>();
+    for (pos, texture_index, color, tilemap_id) in tiles_q.iter(ecs_world) {
+        let (x, y) = ((pos.0.x - min.x) as usize, (pos.0.y - min.y) as usize);
+        if ecs_world.get::<GroundStorage>(tilemap_id.0).is_some() {
+            floor[y][x] = texture_index.0;
+            tints[y][x] = color.0.as_rgba_f32();
+        } else if ecs_world.get::<ObjectStorage>(tilemap_id.0).is_some() {
+            objects[y][x] = Some(texture_index.0);
+        }
+    }
+
+    let world = ecs_world.resource::<GameWorld>();
+    let biomes = (min.y..max.y)
+        .map(|y| {
+            (min.x..max.x)
+                .map(|x| world.biome_at(&TilePos { x, y }).name.clone())
+                .collect()
+        })
+        .collect();
+
+    TileDump {
+        seed,
+        origin: [min.x, min.y],
+        width,
+        height,
+        floor,
+        objects,
+        biomes,
+        items: items
+            .into_iter()
+            .map(|(pos, id)| DumpedItem {
+                x: pos.x,
+                y: pos.y,
+                id: id.0,
+            })
+            .collect(),
+        tints,
+    }
+}
+
+fn write_dump(path: &Path, dump: &TileDump) -> Result<(), Box<dyn Error>> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(dump)?)?;
+    Ok(())
+}
+
+// Draws the layers the same way the tilemaps would, one sprite per tile
+fn write_preview(path: &Path, dump: &TileDump) -> Result<(), Box<dyn Error>> {
+    let terrain = image::open("assets/images/terrain.png")?.into_rgba8();
+    let world_objs = image::open("assets/images/world_objs.png")?.into_rgba8();
+    let items_atlas = image::open("assets/images/items.png")?.into_rgba8();
+    let item_db = load_items_from_json()?;
+
+    let (tile_w, tile_h) = (TILE_PIXELS_X as u32, TILE_PIXELS_Y as u32);
+    let mut preview = RgbaImage::new(dump.width * tile_w, dump.height * tile_h);
+    // Images grow downwards while tile positions grow upwards
+    let pixel_pos = |x: u32, y: u32| ((x * tile_w) as i64, ((dump.height - 1 - y) * tile_h) as i64);
+
+    for y in 0..dump.height {
+        for x in 0..dump.width {
+            let (px, py) = pixel_pos(x, y);
+            let mut floor = atlas_sprite(&terrain, dump.floor[y as usize][x as usize]);
+            let tint = dump.tints[y as usize][x as usize];
+            for pixel in floor.pixels_mut() {
+                for channel in 0..4 {
+                    pixel[channel] = (pixel[channel] as f32 * tint[channel]) as u8;
+                }
+            }
+            imageops::overlay(&mut preview, &floor, px, py);
+
+            if let Some(obj) = dump.objects[y as usize][x as usize] {
+                imageops::overlay(&mut preview, &atlas_sprite(&world_objs, obj), px, py);
+            }
+        }
+    }
+
+    for dumped in dump.items.iter() {
+        if let Some(item) = item_db.iter().find(|item| item.id.0 == dumped.id) {
+            let (px, py) = pixel_pos(dumped.x - dump.origin[0], dumped.y - dump.origin[1]);
+            imageops::overlay(&mut preview, &atlas_sprite(&items_atlas, item.atlas_index), px, py);
+        }
+    }
+
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    preview.save(path)?;
+    Ok(())
+}
+
+fn atlas_sprite(atlas: &RgbaImage, index: u32) -> RgbaImage {
+    let (tile_w, tile_h) = (TILE_PIXELS_X as u32, TILE_PIXELS_Y as u32);
+    let columns = atlas.width() / tile_w;
+    imageops::crop_imm(
+        atlas,
+        (index % columns) * tile_w,
+        (index / columns) * tile_h,
+        tile_w,
+        tile_h,
+    )
+    .to_image()
+}